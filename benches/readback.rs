@@ -0,0 +1,32 @@
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use picalc::{
+    gpu::Gpu,
+    lattice::{Lattice, Readback},
+};
+use wgpu::Features;
+
+const SIZE: usize = 8192;
+
+fn readback(c: &mut Criterion) {
+    let Ok(gpu) = Gpu::acquire_blocking(Features::empty(), 0) else {
+        eprintln!("No GPU Adapter Found, skipping readback benchmarks");
+        return;
+    };
+
+    let mut group = c.benchmark_group("readback");
+    group.sample_size(10);
+    group.throughput(Throughput::Elements((SIZE * SIZE) as u64));
+
+    for readback in Readback::ALL {
+        let lattice = Lattice {
+            readback,
+            ..Lattice::new(SIZE)
+        };
+        group.bench_function(readback.name(), |b| b.iter(|| lattice.count(&gpu).unwrap()));
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, readback);
+criterion_main!(benches);
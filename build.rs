@@ -0,0 +1,16 @@
+use std::fs;
+
+/// Passes on the wgpu version Cargo.lock settled on, since wgpu doesn't say at runtime.
+fn main() {
+    println!("cargo:rerun-if-changed=Cargo.lock");
+
+    let lock = fs::read_to_string("Cargo.lock").unwrap_or_default();
+    let version = lock
+        .split("[[package]]")
+        .find(|package| package.contains("\nname = \"wgpu\"\n"))
+        .and_then(|package| package.lines().find_map(|x| x.strip_prefix("version = ")))
+        .map(|x| x.trim_matches('"'))
+        .unwrap_or("unknown");
+
+    println!("cargo:rustc-env=PICALC_WGPU_VERSION={}", version);
+}
@@ -1,6 +1,21 @@
 struct Options {
-    size: u32,
-    stride: u32
+    // ceil(r^2), so the integer comparison below counts exactly the points with
+    // x^2 + y^2 < r^2 for a fractional radius r
+    radius_squared: u32,
+    side: u32,
+    // 0 circle, 1 superellipse, 2 ellipse, 3 under the square root
+    predicate: u32,
+    // the superellipse exponent or the ellipse's ratio of semi-axes
+    parameter: f32,
+    radius: f32,
+    // which of the sector's bounds to test, 1 for the start and 2 for the end
+    sector: u32,
+    // directions of the rays the sector starts and ends at
+    sector_start: vec2<f32>,
+    sector_end: vec2<f32>,
+    // which way the square's points step from its offset, away from the origin in the
+    // quadrant of the plane being counted
+    quadrant: vec2<i32>,
 }
 
 @group(0) @binding(0)
@@ -8,17 +23,177 @@ var<uniform> options: Options;
 
 @group(1) @binding(0)
 var<storage, read_write> result : array<u32>;
+@group(1) @binding(0)
+var<storage, read_write> total : atomic<u32>;
+struct Offset {
+    // the square's corner nearest the origin, mirrored into the quadrant being counted
+    point: vec2<i32>,
+    // how far the square's points are moved within their unit cells, 0 for the lattice
+    jitter: vec2<f32>,
+}
+
 @group(1) @binding(1)
-var<uniform> offset: vec2<u32>;
+var<uniform> offset: Offset;
+
+var<workgroup> workgroup_total: atomic<u32>;
+
+// pow is undefined for a zero base
+fn power(base: f32, exponent: f32) -> f32 {
+    if base == 0.0 {
+        return 0.0;
+    }
+    return pow(base, exponent);
+}
+
+// positive when the point is anticlockwise of the ray
+fn side_of(ray: vec2<f32>, point: vec2<f32>) -> f32 {
+    return ray.x * point.y - ray.y * point.x;
+}
+
+// on the start ray is in, on the end ray is out
+fn in_sector(p: vec2<f32>) -> bool {
+    var after_start = (options.sector & 1u) == 0u || side_of(options.sector_start, p) >= 0.0;
+    var before_end = (options.sector & 2u) == 0u || side_of(options.sector_end, p) < 0.0;
+    return after_start && before_end;
+}
+
+fn lattice_point(cell: vec2<u32>) -> vec2<i32> {
+    return offset.point + vec2<i32>(cell) * options.quadrant;
+}
+
+// x^2 + y^2 wraps in an i32 past 46340. Each of x^2 and y^2 fits a u32 on a grid of at
+// most 65536, but their sum doesn't, so it stops at the largest u32, which is outside any
+// radius short of 65536
+fn squared_distance(point: vec2<i32>) -> u32 {
+    var squares = bitcast<vec2<u32>>(point * point);
+    var sum = squares.x + squares.y;
+    return select(sum, 0xffffffffu, sum < squares.x);
+}
+
+fn is_inside(cell: vec2<u32>) -> u32 {
+    var point = lattice_point(cell);
+    // moved away from the origin, like the point's cell
+    var sample = vec2<f32>(point) + offset.jitter * vec2<f32>(options.quadrant);
+
+    // the sector's rays are in the first quadrant, so it's mirrored along with the points
+    if !in_sector(abs(sample)) {
+        return 0u;
+    }
+
+    switch options.predicate {
+        case 1u: {
+            var scaled = abs(sample) / options.radius;
+            return u32(power(scaled.x, options.parameter) + power(scaled.y, options.parameter) < 1.0);
+        }
+        case 2u: {
+            var scaled = sample / (options.radius * vec2<f32>(1.0, options.parameter));
+            return u32(dot(scaled, scaled) < 1.0);
+        }
+        case 3u: {
+            return u32(sample.y * sample.y < options.radius * (options.radius - abs(sample.x)));
+        }
+        default: {
+            // off the lattice there's no exact integer test to fall back on
+            if any(offset.jitter != vec2<f32>(0.0)) {
+                return u32(dot(sample, sample) < options.radius * options.radius);
+            }
 
+            return u32(squared_distance(point) < options.radius_squared);
+        }
+    }
+}
+
+// One u32 per point
 @compute @workgroup_size(16, 16, 1)
 fn main(@builtin(global_invocation_id) invocation_id: vec3<u32>) {
-    var point = invocation_id.xy + offset;
-    var distance = (point.x* point.x + point.y * point.y);
-    var is_in_circle = distance < (options.size - 1u) * (options.size - 1u);
-
-    var res = u32(is_in_circle);
+    var res = is_inside(invocation_id.xy);
     //var res = distance;
 
-    result[options.stride * invocation_id.y + invocation_id.x] = res;
-}
\ No newline at end of file
+    result[options.side * invocation_id.y + invocation_id.x] = res;
+}
+
+// One u32 per row, each thread walking its whole row
+@compute @workgroup_size(16, 1, 1)
+fn rows(@builtin(global_invocation_id) invocation_id: vec3<u32>) {
+    var count = 0u;
+    for (var x = 0u; x < options.side; x++) {
+        count += is_inside(vec2<u32>(x, invocation_id.x));
+    }
+
+    result[invocation_id.x] = count;
+}
+
+// A single u32 for the whole square, summed within each workgroup before touching the
+// global total
+@compute @workgroup_size(16, 16, 1)
+fn reduce(
+    @builtin(global_invocation_id) invocation_id: vec3<u32>,
+    @builtin(local_invocation_index) local_index: u32,
+) {
+    // not every backend zeroes workgroup memory for us
+    if local_index == 0u {
+        atomicStore(&workgroup_total, 0u);
+    }
+    workgroupBarrier();
+
+    atomicAdd(&workgroup_total, is_inside(invocation_id.xy));
+    workgroupBarrier();
+
+    if local_index == 0u {
+        atomicAdd(&total, atomicLoad(&workgroup_total));
+    }
+}
+
+// One bit per point, 32 consecutive points of a row to a word
+@compute @workgroup_size(16, 16, 1)
+fn bitpacked(@builtin(global_invocation_id) invocation_id: vec3<u32>) {
+    var words = (options.side + 31u) / 32u;
+    if invocation_id.x >= words {
+        return;
+    }
+
+    var bits = 0u;
+    for (var bit = 0u; bit < 32u; bit++) {
+        var x = invocation_id.x * 32u + bit;
+        if x < options.side {
+            bits |= is_inside(vec2<u32>(x, invocation_id.y)) << bit;
+        }
+    }
+
+    result[words * invocation_id.y + invocation_id.x] = bits;
+}
+
+// A single u32 for the whole square from a single workgroup, each thread looping over
+// every 16th point both ways, which takes the tiling out of the picture when debugging
+@compute @workgroup_size(16, 16, 1)
+fn single_workgroup(
+    @builtin(local_invocation_id) local_id: vec3<u32>,
+    @builtin(local_invocation_index) local_index: u32,
+) {
+    if local_index == 0u {
+        atomicStore(&workgroup_total, 0u);
+    }
+    workgroupBarrier();
+
+    var count = 0u;
+    for (var y = local_id.y; y < options.side; y += 16u) {
+        for (var x = local_id.x; x < options.side; x += 16u) {
+            count += is_inside(vec2<u32>(x, y));
+        }
+    }
+    atomicAdd(&workgroup_total, count);
+    workgroupBarrier();
+
+    if local_index == 0u {
+        atomicStore(&total, atomicLoad(&workgroup_total));
+    }
+}
+
+// The squared distance from the origin of each point inside, and 0xffffffff for the rest
+@compute @workgroup_size(16, 16, 1)
+fn distances(@builtin(global_invocation_id) invocation_id: vec3<u32>) {
+    var distance = squared_distance(lattice_point(invocation_id.xy));
+    var res = select(0xffffffffu, distance, is_inside(invocation_id.xy) == 1u);
+
+    result[options.side * invocation_id.y + invocation_id.x] = res;
+}
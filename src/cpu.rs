@@ -0,0 +1,96 @@
+//! Straightforward CPU versions of the GPU methods, used to check their results.
+
+use std::f64::consts::TAU;
+
+use crate::lattice::Predicate;
+
+/// Counts the lattice points `x, y >= 0` inside the region, one column at a time.
+///
+/// The comparison is done by [`Predicate::contains`] in f64. For the circle that is exact
+/// for any f32 `r` and lattice coordinates up to 2^26, and doesn't go through the integer
+/// bound the shader uses.
+pub fn lattice_count(predicate: Predicate, radius: f32) -> u64 {
+    let inside = |x: u64, y: u64| predicate.contains(radius, x, y);
+
+    let width = radius.ceil() as u64;
+
+    let mut total = 0;
+    let mut height = width;
+    for x in 0..width {
+        // every region only gets lower moving right, so each column starts from the
+        // previous column's height
+        while height > 0 && !inside(x, height - 1) {
+            height -= 1;
+        }
+        total += height;
+    }
+
+    total
+}
+
+/// Counts the lattice points close enough to the boundary that the GPU's f32 rounding
+/// could put them on either side, so a float predicate's count can be checked to within
+/// that many points. Always 0 for the circle, which the GPU counts exactly.
+pub fn ambiguous_count(predicate: Predicate, radius: f32) -> u64 {
+    if predicate == Predicate::Circle {
+        return 0;
+    }
+
+    lattice_count(predicate, radius * (1.0 + 1e-5))
+        - lattice_count(predicate, radius * (1.0 - 1e-5))
+}
+
+/// Counts the lattice points `x, y >= 0` exactly on the circle, which an inclusive count
+/// takes in and a strict one leaves out. There are none unless `r^2` is a whole number.
+pub fn circle_points(radius: f32) -> u64 {
+    let radius_squared = radius as f64 * radius as f64;
+
+    (0..=radius.floor() as u64)
+        .filter(|&x| {
+            let rest = radius_squared - (x * x) as f64;
+            let y = rest.sqrt().round();
+            y * y == rest
+        })
+        .count() as u64
+}
+
+/// Counts the points landing inside the quarter circle, `x^2 + y^2 < 1` in f32 as the
+/// GPU tests them. A GPU that fuses the multiply and add can round a point right on the
+/// edge the other way, which points of up to 12 bits after the point never are, their
+/// squares adding up exactly.
+pub fn sample_hits(points: &[[f32; 2]]) -> u64 {
+    points.iter().filter(|[x, y]| x * x + y * y < 1.0).count() as u64
+}
+
+/// Four times the sum of the Leibniz series' first `terms` terms, in f64, added up from
+/// the smallest term so the big ones don't swamp them.
+pub fn leibniz(terms: u64) -> f64 {
+    4.0 * (0..terms)
+        .rev()
+        .map(|k| {
+            let term = 1.0 / (2 * k + 1) as f64;
+            if k % 2 == 0 {
+                term
+            } else {
+                -term
+            }
+        })
+        .sum::<f64>()
+}
+
+/// The shoelace area of the regular polygon with `sides` vertices on the unit circle, in
+/// f64.
+pub fn polygon_area(sides: u32) -> f64 {
+    let vertex = |k: u32| {
+        let angle = TAU * (k % sides) as f64 / sides as f64;
+        (angle.cos(), angle.sin())
+    };
+
+    (0..sides)
+        .map(|k| {
+            let (a, b) = (vertex(k), vertex(k + 1));
+            a.0 * b.1 - b.0 * a.1
+        })
+        .sum::<f64>()
+        / 2.0
+}
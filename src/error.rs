@@ -0,0 +1,67 @@
+use std::{
+    error::Error,
+    fmt::{self, Display},
+};
+
+use crate::lattice::MAX_SIZE;
+
+/// Ways a computation can go wrong that are worth telling apart from a bug in the caller.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PiError {
+    /// A word past the end of a square's results didn't hold the canary it was filled
+    /// with, so the shader or its dispatch wrote outside the square.
+    BufferCorruption {
+        /// Offset of the square whose buffer was written past.
+        square: [u32; 2],
+        /// Index of the first clobbered word.
+        word: usize,
+        found: u32,
+    },
+    /// A point of a square was counted differently from what the region holds at the
+    /// square's offset.
+    OffsetMismatch { point: [u32; 2] },
+    /// A lattice too small to leave a nonzero radius, or bigger than [`MAX_SIZE`].
+    BadSize { size: usize },
+    /// No adapter turned up, even after waiting and looking again.
+    NoAdapter { attempts: u32 },
+    /// The adapter turned up but wouldn't give us a device.
+    NoDevice { adapter: String, reason: String },
+    /// The adapter is too limited to run the shaders, as WebGL2 and GLES 3.0 are.
+    Unsupported {
+        adapter: String,
+        missing: &'static str,
+    },
+}
+
+impl Display for PiError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::BufferCorruption {
+                square: [x, y],
+                word,
+                found,
+            } => write!(
+                f,
+                "Buffer Corruption: Square At ({}, {}) Wrote {:#010x} Past Its End At Word {}",
+                x, y, found, word
+            ),
+            Self::OffsetMismatch { point: [x, y] } => {
+                write!(f, "Square Offset Miscounted Point ({}, {})", x, y)
+            }
+            Self::BadSize { size } => {
+                write!(f, "Lattice Size {} Isn't From 2 To {}", size, MAX_SIZE)
+            }
+            Self::NoAdapter { attempts } => {
+                write!(f, "No GPU Adapter Found After {} Attempts", attempts)
+            }
+            Self::NoDevice { adapter, reason } => {
+                write!(f, "Adapter {} Gave No Device: {}", adapter, reason)
+            }
+            Self::Unsupported { adapter, missing } => {
+                write!(f, "Adapter {} Lacks {}", adapter, missing)
+            }
+        }
+    }
+}
+
+impl Error for PiError {}
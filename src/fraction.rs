@@ -0,0 +1,61 @@
+use std::fmt::{self, Display};
+
+/// An exact estimate of pi, kept as the count based ratio every method produces.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Fraction {
+    pub numerator: u64,
+    pub denominator: u64,
+}
+
+impl Fraction {
+    pub fn new(numerator: u64, denominator: u64) -> Self {
+        Self {
+            numerator,
+            denominator,
+        }
+    }
+
+    /// The same value in lowest terms.
+    pub fn reduced(self) -> Self {
+        let divisor = gcd(self.numerator, self.denominator).max(1);
+        Self::new(self.numerator / divisor, self.denominator / divisor)
+    }
+
+    /// The value truncated to `digits` decimal places. Done by long division in u128, so
+    /// every digit is exact however many are asked for.
+    pub fn decimal(self, digits: usize) -> String {
+        assert!(self.denominator != 0, "Fraction {} Has No Decimal", self);
+
+        let denominator = self.denominator as u128;
+        let mut remainder = self.numerator as u128 % denominator;
+
+        let mut decimal = (self.numerator / self.denominator).to_string();
+        if digits > 0 {
+            decimal.push('.');
+        }
+        for _ in 0..digits {
+            remainder *= 10;
+            decimal.push(char::from_digit((remainder / denominator) as u32, 10).unwrap());
+            remainder %= denominator;
+        }
+
+        decimal
+    }
+
+    pub fn to_f64(self) -> f64 {
+        self.numerator as f64 / self.denominator as f64
+    }
+}
+
+impl Display for Fraction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}", self.numerator, self.denominator)
+    }
+}
+
+fn gcd(mut a: u64, mut b: u64) -> u64 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
@@ -0,0 +1,248 @@
+use std::{cmp::Ordering, path::Path, thread, time::Duration};
+
+use wgpu::{
+    Adapter, AdapterInfo, Backends, Device, DeviceType, DownlevelCapabilities, DownlevelFlags,
+    Features, Instance, InstanceDescriptor, Maintain, Queue, RequestDeviceError, SubmissionIndex,
+};
+
+use crate::error::PiError;
+
+/// Times to look for an adapter again before giving up, unless told otherwise.
+pub const DEFAULT_RETRIES: u32 = 2;
+
+/// How long to wait before looking for an adapter again, doubling after every miss.
+const FIRST_RETRY_DELAY: Duration = Duration::from_millis(250);
+
+/// The wgpu objects every method needs to run a compute pass.
+pub struct Gpu {
+    pub instance: Instance,
+    pub device: Device,
+    pub queue: Queue,
+    /// The adapter the device came from.
+    pub info: AdapterInfo,
+    /// What the adapter can't do that WebGPU would, as on WebGL and older GLES.
+    pub downlevel: DownlevelCapabilities,
+}
+
+impl Gpu {
+    /// Sets up the best available adapter, turning on whichever of `features` it supports.
+    /// What was turned on is in `device.features()`. Looks again up to `retries` times when
+    /// there's no adapter yet, as happens while a driver is still loading after boot, and
+    /// fails with [`PiError::NoAdapter`] when there's still none. Fails with
+    /// [`PiError::Unsupported`] for an adapter that can't run the shaders, and with
+    /// [`PiError::NoDevice`] for one that won't give us a device.
+    ///
+    /// The waits between looks block the calling thread rather than yielding, 750 ms in all
+    /// with [`DEFAULT_RETRIES`], since wgpu has no async timer to hand. On an executor whose
+    /// thread other tasks need, pass 0 retries or call this from a thread of its own.
+    pub async fn acquire(features: Features, retries: u32) -> Result<Self, PiError> {
+        Self::acquire_traced(features, retries, None).await
+    }
+
+    /// Like [`Gpu::acquire`], with wgpu writing every call made on the device into a trace
+    /// in the `trace` directory, which has to exist already, to be replayed with wgpu's
+    /// player. Only built with the `trace` feature, since tracing slows everything down a
+    /// lot and is only for when a driver needs showing what we did to it.
+    #[cfg(feature = "trace")]
+    pub async fn acquire_with_trace(
+        features: Features,
+        retries: u32,
+        trace: &Path,
+    ) -> Result<Self, PiError> {
+        Self::acquire_traced(features, retries, Some(trace)).await
+    }
+
+    async fn acquire_traced(
+        features: Features,
+        retries: u32,
+        trace: Option<&Path>,
+    ) -> Result<Self, PiError> {
+        let mut delay = FIRST_RETRY_DELAY;
+        for attempt in 0..=retries {
+            if let Some(gpu) = Self::traced(features, trace).await? {
+                gpu.check_support()?;
+                return Ok(gpu);
+            }
+            if attempt < retries {
+                log::warn!(
+                    "No adapter found, looking again in {} ms ({} of {} retries)",
+                    delay.as_millis(),
+                    attempt + 1,
+                    retries
+                );
+                thread::sleep(delay);
+                delay *= 2;
+            }
+        }
+
+        Err(PiError::NoAdapter {
+            attempts: retries + 1,
+        })
+    }
+
+    /// `Ok(None)` when there's no adapter, which is worth looking for again, unlike an
+    /// adapter that turned up and refused us a device.
+    async fn traced(features: Features, trace: Option<&Path>) -> Result<Option<Self>, PiError> {
+        let instance = wgpu::Instance::new(InstanceDescriptor::default());
+
+        let Some(adapter) = select_adapter(&instance) else {
+            return Ok(None);
+        };
+        let features = features & adapter.features();
+
+        Self::from_adapter(instance, &adapter, features, trace)
+            .await
+            .map(Some)
+            .map_err(|e| PiError::NoDevice {
+                adapter: adapter.get_info().name,
+                reason: e.to_string(),
+            })
+    }
+
+    /// [`Gpu::acquire`] for callers that aren't async, blocking the thread until it's done.
+    /// Not to be called from inside an async runtime, whose thread it would hold up.
+    pub fn acquire_blocking(features: Features, retries: u32) -> Result<Self, PiError> {
+        pollster::block_on(Self::acquire(features, retries))
+    }
+
+    /// Sets up every available adapter in turn, best first. Adapters that can't give us a
+    /// device come back as `Err`, so one broken driver doesn't hide the rest.
+    pub async fn all() -> Vec<(AdapterInfo, Result<Self, RequestDeviceError>)> {
+        let found = sorted_adapters(&Instance::new(InstanceDescriptor::default())).len();
+
+        let mut gpus = Vec::with_capacity(found);
+        // a Gpu owns its instance, so each one enumerates again on a fresh one
+        for i in 0..found {
+            let instance = Instance::new(InstanceDescriptor::default());
+            let Some((info, adapter)) = sorted_adapters(&instance).into_iter().nth(i) else {
+                break;
+            };
+            gpus.push((
+                info,
+                Self::from_adapter(instance, &adapter, Features::empty(), None).await,
+            ));
+        }
+
+        gpus
+    }
+
+    /// Checks the adapter can run every shader, rather than leaving a limited one to fail
+    /// validation somewhere in the middle of a count. The lattice's workgroups are 16 x 16
+    /// and the reductions' 64 x 1, and both write their results to storage buffers.
+    pub fn check_support(&self) -> Result<(), PiError> {
+        let limits = self.device.limits();
+
+        let missing = if !self
+            .downlevel
+            .flags
+            .contains(DownlevelFlags::COMPUTE_SHADERS)
+        {
+            Some("Compute Shaders")
+        } else if limits.max_storage_buffers_per_shader_stage == 0 {
+            Some("Storage Buffers")
+        } else if limits.max_compute_invocations_per_workgroup < 256
+            || limits.max_compute_workgroup_size_x < 64
+            || limits.max_compute_workgroup_size_y < 16
+        {
+            Some("Workgroups Of 256 Invocations")
+        } else {
+            None
+        };
+
+        match missing {
+            Some(missing) => Err(PiError::Unsupported {
+                adapter: self.info.name.clone(),
+                missing,
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Blocks until `submission` has finished on the GPU and the buffers it used that were
+    /// waiting to be mapped are, leaving anything submitted since to carry on.
+    pub fn wait(&self, submission: SubmissionIndex) {
+        self.device
+            .poll(Maintain::WaitForSubmissionIndex(submission));
+    }
+
+    async fn from_adapter(
+        instance: Instance,
+        adapter: &Adapter,
+        features: Features,
+        trace: Option<&Path>,
+    ) -> Result<Self, RequestDeviceError> {
+        let (device, queue) = adapter
+            .request_device(
+                &wgpu::DeviceDescriptor {
+                    label: None,
+                    features,
+                    limits: adapter.limits(),
+                },
+                trace,
+            )
+            .await?;
+
+        Ok(Self {
+            instance,
+            device,
+            queue,
+            info: adapter.get_info(),
+            downlevel: adapter.get_downlevel_capabilities(),
+        })
+    }
+}
+
+/// Lower is better: real hardware first, discrete over integrated, then anything emulated.
+fn device_type_rank(device_type: DeviceType) -> u8 {
+    match device_type {
+        DeviceType::DiscreteGpu => 0,
+        DeviceType::IntegratedGpu => 1,
+        DeviceType::VirtualGpu => 2,
+        DeviceType::Cpu => 3,
+        DeviceType::Other => 4,
+    }
+}
+
+fn compare_adapters(a: &AdapterInfo, b: &AdapterInfo) -> Ordering {
+    device_type_rank(a.device_type)
+        .cmp(&device_type_rank(b.device_type))
+        .then_with(|| a.name.cmp(&b.name))
+        // the same device is often exposed through more than one backend
+        .then_with(|| (a.backend as u8).cmp(&(b.backend as u8)))
+}
+
+fn sorted_adapters(instance: &Instance) -> Vec<(AdapterInfo, Adapter)> {
+    let mut adapters = instance
+        .enumerate_adapters(Backends::all())
+        .map(|adapter| (adapter.get_info(), adapter))
+        .collect::<Vec<_>>();
+    adapters.sort_by(|(a, _), (b, _)| compare_adapters(a, b));
+    adapters
+}
+
+/// `request_adapter` leaves the choice to the driver, which tends to hand out the
+/// integrated GPU. Ranking every adapter ourselves keeps the default predictable.
+fn select_adapter(instance: &Instance) -> Option<Adapter> {
+    let adapters = sorted_adapters(instance);
+
+    for (info, _) in &adapters {
+        log::debug!(
+            "Found adapter {} ({:?}, {:?})",
+            info.name,
+            info.device_type,
+            info.backend
+        );
+    }
+
+    let found = adapters.len();
+    let (info, adapter) = adapters.into_iter().next()?;
+    log::info!(
+        "Auto-selected adapter {} ({:?}, {:?}) out of {}: discrete GPUs are preferred, then integrated, virtual and CPU adapters, with ties broken by name",
+        info.name,
+        info.device_type,
+        info.backend,
+        found
+    );
+
+    Some(adapter)
+}
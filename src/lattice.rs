@@ -0,0 +1,1759 @@
+use std::{
+    cmp::Ordering,
+    collections::HashMap,
+    f64::consts::PI,
+    mem::size_of,
+    ops::Range,
+    str::FromStr,
+    sync::{Arc, Mutex},
+};
+
+use wgpu::{
+    include_wgsl, util::DeviceExt, BindGroup, BindGroupLayout, Buffer, BufferAddress,
+    BufferDescriptor, BufferUsages, CommandEncoder, ComputePass, Device, Features, Limits, MapMode,
+    SubmissionIndex,
+};
+
+use crate::{
+    error::PiError,
+    fraction::Fraction,
+    gpu::{self, Gpu},
+    mapped,
+};
+
+/// How a square's counts are laid out for the trip back to the host.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Readback {
+    /// One u32 per lattice point.
+    Grid,
+    /// One u32 per row of the square, each thread counting a whole row.
+    Rows,
+    /// A single u32 per square, summed on the GPU with atomics.
+    Reduce,
+    /// One bit per lattice point, 32 to a u32.
+    Bitpacked,
+    /// A single u32 per square like [`Readback::Reduce`], but counted by one workgroup
+    /// looping over the whole square. Slow, and only for ruling the tiling in or out when
+    /// a count is wrong, so it's left out of [`Readback::ALL`].
+    SingleWorkgroup,
+}
+
+impl Readback {
+    pub const ALL: [Self; 4] = [Self::Grid, Self::Rows, Self::Reduce, Self::Bitpacked];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Grid => "grid",
+            Self::Rows => "rows",
+            Self::Reduce => "reduce",
+            Self::Bitpacked => "bitpacked",
+            Self::SingleWorkgroup => "single-workgroup",
+        }
+    }
+
+    fn entry_point(self) -> &'static str {
+        match self {
+            Self::Grid => "main",
+            Self::Rows => "rows",
+            Self::Reduce => "reduce",
+            Self::Bitpacked => "bitpacked",
+            Self::SingleWorkgroup => "single_workgroup",
+        }
+    }
+
+    /// Number of u32s read back for a square `side` points across.
+    fn words(self, side: u32) -> u32 {
+        match self {
+            Self::Grid => side * side,
+            Self::Rows => side,
+            Self::Reduce | Self::SingleWorkgroup => 1,
+            Self::Bitpacked => side.div_ceil(32) * side,
+        }
+    }
+
+    /// The entry point's `@workgroup_size`, which has to match compute.wgsl.
+    fn workgroup_size(self) -> [u32; 2] {
+        match self {
+            Self::Rows => [16, 1],
+            _ => [16, 16],
+        }
+    }
+
+    /// How many points along x and y each thread takes care of, for a square `side`
+    /// points across.
+    fn cells_per_thread(self, side: u32) -> [u32; 2] {
+        match self {
+            Self::Rows => [side, 1],
+            Self::Bitpacked => [32, 1],
+            Self::SingleWorkgroup => [side / 16, side / 16],
+            _ => [1, 1],
+        }
+    }
+
+    /// Workgroups to dispatch along each axis for a square `side` points across.
+    fn dispatch_dims(self, side: u32) -> [u32; 2] {
+        match self {
+            Self::Grid | Self::Reduce => [side / 16, side / 16],
+            Self::Rows => [side / 16, 1],
+            Self::Bitpacked => [side.div_ceil(32).div_ceil(16), side / 16],
+            Self::SingleWorkgroup => [1, 1],
+        }
+    }
+
+    /// Threads dispatched along the square's x and y for a square `side` points across.
+    fn threads(self, side: u32) -> [u32; 2] {
+        let dims = self.dispatch_dims(side);
+        let workgroup_size = self.workgroup_size();
+        let mut threads = [0, 1].map(|axis| dims[axis] * workgroup_size[axis]);
+        // rows lines its threads up along x but hands each one a row
+        if self == Self::Rows {
+            threads.reverse();
+        }
+        threads
+    }
+
+    /// Checks the dispatch covers every point of the square exactly once. Too few threads
+    /// would silently drop points and too many would write past the end of the buffer,
+    /// except along bitpacked's x, where the shader cuts the last workgroup short.
+    fn check_dispatch(self, side: u32) {
+        let workgroup_size = self.workgroup_size();
+        let threads = self.threads(side);
+
+        let cells_per_thread = self.cells_per_thread(side);
+        for axis in 0..2 {
+            let covered = threads[axis] * cells_per_thread[axis];
+            let slack = match (self, axis) {
+                (Self::Bitpacked, 0) => workgroup_size[0] * cells_per_thread[0],
+                _ => 1,
+            };
+            debug_assert!(
+                covered >= side && covered - side < slack,
+                "Dispatch Of {:?} Covers {} Of {} Points Along Axis {}",
+                self,
+                covered,
+                side,
+                axis
+            );
+        }
+    }
+}
+
+impl FromStr for Readback {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL.into_iter().find(|x| x.name() == s).ok_or(())
+    }
+}
+
+struct Square {
+    bind_group: BindGroup,
+    /// The storage buffer's bytes from each offset on are copied into its buffer, in one
+    /// piece unless the lattice reads back in [`Lattice::ranges`]. Empty when the storage
+    /// buffer is mapped for reading itself.
+    read_buffers: Vec<(BufferAddress, Buffer)>,
+    storage_buffer: Buffer,
+    offset: [u32; 2],
+    _offset_buffer: Buffer,
+
+    side: u32,
+    readback: Readback,
+}
+struct SquareCommonOptions<'a> {
+    device: &'a Device,
+    side: u32,
+    readback: Readback,
+
+    storage_buffer_descriptor: &'a BufferDescriptor<'a>,
+    /// The parts of the storage buffer to copy into read buffers of their own.
+    read_ranges: &'a [Range<BufferAddress>],
+
+    bind_group_layout: &'a BindGroupLayout,
+}
+impl Square {
+    /// The square at `offset` in the first quadrant, counted from `mirrored`, its corner
+    /// nearest the origin in the quadrant being counted.
+    fn new(
+        offset: [u32; 2],
+        mirrored: [i32; 2],
+        jitter: [f32; 2],
+        common_opts: &SquareCommonOptions,
+    ) -> Self {
+        let _offset_buffer =
+            common_opts
+                .device
+                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("Bottom Right Offset Buffer"),
+                    contents: bytemuck::cast_slice(&[
+                        mirrored[0] as u32,
+                        mirrored[1] as u32,
+                        jitter[0].to_bits(),
+                        jitter[1].to_bits(),
+                    ]),
+                    usage: BufferUsages::UNIFORM,
+                });
+        let storage_buffer = common_opts
+            .device
+            .create_buffer(common_opts.storage_buffer_descriptor);
+        {
+            let words = common_opts.readback.words(common_opts.side) as usize;
+            let mut data = storage_buffer.slice(..).get_mapped_range_mut();
+            mapped::words_mut(&mut data)[words..].fill(CANARY);
+        }
+        storage_buffer.unmap();
+        let read_buffers = common_opts
+            .read_ranges
+            .iter()
+            .map(|range| {
+                let read_buffer = common_opts.device.create_buffer(&BufferDescriptor {
+                    label: Some("Read Buffer"),
+                    size: range.end - range.start,
+                    usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+                    mapped_at_creation: false,
+                });
+                (range.start, read_buffer)
+            })
+            .collect();
+
+        let bind_group = common_opts
+            .device
+            .create_bind_group(&wgpu::BindGroupDescriptor {
+                label: None,
+                layout: common_opts.bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: storage_buffer.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: _offset_buffer.as_entire_binding(),
+                    },
+                ],
+            });
+
+        Self {
+            offset,
+            _offset_buffer,
+            storage_buffer,
+            read_buffers,
+            bind_group,
+            side: common_opts.side,
+            readback: common_opts.readback,
+        }
+    }
+
+    fn compute<'a>(&'a self, cpass: &mut ComputePass<'a>) {
+        let side = self.side;
+
+        self.readback.check_dispatch(side);
+        let [x, y] = self.readback.dispatch_dims(side);
+
+        cpass.set_bind_group(1, &self.bind_group, &[]);
+        cpass.dispatch_workgroups(x, y, 1);
+    }
+
+    /// Whichever buffer the results are read from, when they're read back in one piece.
+    fn readable(&self) -> &Buffer {
+        debug_assert!(self.read_buffers.len() <= 1, "Square Read Back In Pieces");
+        self.read_buffers
+            .first()
+            .map_or(&self.storage_buffer, |(_, read_buffer)| read_buffer)
+    }
+
+    /// Each buffer the results are read from with the word of the storage buffer it starts
+    /// at.
+    fn pieces(&self) -> Vec<(usize, &Buffer)> {
+        match self.read_buffers.is_empty() {
+            true => vec![(0, &self.storage_buffer)],
+            false => self
+                .read_buffers
+                .iter()
+                .map(|(offset, read_buffer)| (*offset as usize / size_of::<u32>(), read_buffer))
+                .collect(),
+        }
+    }
+
+    /// Copies the `piece`th read buffer's part of the storage buffer into it.
+    fn copy(&self, encoder: &mut CommandEncoder, piece: usize) {
+        if let Some((offset, read_buffer)) = self.read_buffers.get(piece) {
+            encoder.copy_buffer_to_buffer(
+                &self.storage_buffer,
+                *offset,
+                read_buffer,
+                0,
+                read_buffer.size(),
+            );
+        }
+    }
+
+    /// Checks nothing past the square's words was written to.
+    fn check_canary(&self) -> Result<(), PiError> {
+        let data = self.readable().slice(..).get_mapped_range();
+        let words = self.readback.words(self.side) as usize;
+
+        let canary: &[u32] = &mapped::words(&data)[words..];
+        match canary.iter().position(|&x| x != CANARY) {
+            Some(i) => Err(PiError::BufferCorruption {
+                square: self.offset,
+                word: words + i,
+                found: canary[i],
+            }),
+            None => Ok(()),
+        }
+    }
+
+    fn map(&self) {
+        self.readable()
+            .slice(..)
+            .map_async(MapMode::Read, move |e| {
+                e.unwrap();
+            });
+    }
+
+    /// Adds up the points counted in `data`, the square's words from `start` on, checking
+    /// the canary among them too.
+    fn tally(&self, start: usize, data: &[u32]) -> Result<u32, PiError> {
+        // anything past the square's words is canary
+        let words = self.readback.words(self.side) as usize;
+        let (counts, canary) = data.split_at(words.saturating_sub(start).min(data.len()));
+
+        if let Some(i) = canary.iter().position(|&x| x != CANARY) {
+            return Err(PiError::BufferCorruption {
+                square: self.offset,
+                word: start + counts.len() + i,
+                found: canary[i],
+            });
+        }
+
+        Ok(match self.readback {
+            Readback::Bitpacked => counts.iter().map(|x| x.count_ones()).sum(),
+            _ => counts.iter().sum(),
+        })
+    }
+
+    fn cells(&self) -> Vec<u32> {
+        let data = self.readable().slice(..).get_mapped_range();
+
+        mapped::words(&data)[..self.readback.words(self.side) as usize].to_vec()
+    }
+
+    #[allow(dead_code)]
+    fn print(&self) {
+        let data = self.readable().slice(..).get_mapped_range();
+
+        let data_u32: &[u32] = mapped::words(&data);
+
+        for y in 0..self.side {
+            for x in 0..self.side {
+                print!("{}", data_u32[(y * self.side + x) as usize]);
+            }
+            println!();
+        }
+    }
+}
+
+/// Which region of the first quadrant the lattice points are counted in.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Predicate {
+    /// `x^2 + y^2 < r^2`, counted exactly in integers. Converges to pi.
+    Circle,
+    /// `x^p + y^p < r^p`, in f32 on the GPU. Normalized by `r^2` like the circle it
+    /// converges to `4 Γ(1 + 1/p)^2 / Γ(1 + 2/p)`: 2 for the diamond `p = 1`, pi for
+    /// `p = 2`, about 3.7081 for the squircle `p = 4`, and on towards 4 as `p` grows.
+    Superellipse(f32),
+    /// `(x / r)^2 + (y / (r * ratio))^2 < 1` for `0 < ratio <= 1`, in f32 on the GPU.
+    /// Normalized by the product of the semi-axes it converges to pi.
+    Ellipse(f32),
+    /// `y < sqrt(r (r - x))`, in f32 on the GPU: the area under `sqrt` from 0 to `r`,
+    /// mirrored to shrink away from the origin like the other regions. Normalized by `r^2`
+    /// it converges to `4 * 2/3 = 8/3`, showing counting as integration of any monotone
+    /// curve.
+    Sqrt,
+}
+
+impl Predicate {
+    /// Tells the shader which predicate to evaluate.
+    fn id(self) -> u32 {
+        match self {
+            Self::Circle => 0,
+            Self::Superellipse(_) => 1,
+            Self::Ellipse(_) => 2,
+            Self::Sqrt => 3,
+        }
+    }
+
+    fn parameter(self) -> f32 {
+        match self {
+            Self::Circle | Self::Sqrt => 0.0,
+            Self::Superellipse(p) => p,
+            Self::Ellipse(ratio) => ratio,
+        }
+    }
+
+    /// Whether the point is inside the region, evaluated in f64.
+    pub fn contains(self, radius: f32, x: u64, y: u64) -> bool {
+        let radius = radius as f64;
+        let (x, y) = (x as f64, y as f64);
+
+        match self {
+            Self::Circle => x * x + y * y < radius * radius,
+            Self::Superellipse(p) => {
+                (x / radius).powf(p as f64) + (y / radius).powf(p as f64) < 1.0
+            }
+            Self::Ellipse(ratio) => {
+                let (x, y) = (x / radius, y / (radius * ratio as f64));
+                x * x + y * y < 1.0
+            }
+            Self::Sqrt => y * y < radius * (radius - x),
+        }
+    }
+
+    /// How far from the origin the region reaches.
+    pub fn extent(self, radius: f32) -> f64 {
+        let radius = radius as f64;
+
+        match self {
+            Self::Circle | Self::Ellipse(_) | Self::Sqrt => radius,
+            // the superellipse bulges furthest along the diagonal when p > 2
+            Self::Superellipse(p) => radius * 2f64.powf(0.5 - 1.0 / p as f64).max(1.0),
+        }
+    }
+
+    /// The area the quarter region's count is divided by, before the factor of 4.
+    pub fn normalizer(self, radius: f32) -> f64 {
+        let radius = radius as f64;
+
+        match self {
+            Self::Circle | Self::Superellipse(_) | Self::Sqrt => radius * radius,
+            Self::Ellipse(ratio) => radius * radius * ratio as f64,
+        }
+    }
+
+    /// The value `4 * count / normalizer` converges to.
+    pub fn constant(self) -> f64 {
+        match self {
+            Self::Circle | Self::Ellipse(_) => PI,
+            Self::Superellipse(p) => {
+                let p = p as f64;
+                4.0 * gamma(1.0 + 1.0 / p).powi(2) / gamma(1.0 + 2.0 / p)
+            }
+            Self::Sqrt => 8.0 / 3.0,
+        }
+    }
+}
+
+impl FromStr for Predicate {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, parameter) = match s.split_once(':') {
+            Some((name, parameter)) => (name, Some(parameter.parse::<f32>().map_err(|_| ())?)),
+            None => (s, None),
+        };
+
+        match (name, parameter) {
+            ("circle", None) => Ok(Self::Circle),
+            ("sqrt", None) => Ok(Self::Sqrt),
+            ("superellipse", Some(p)) if p > 0.0 => Ok(Self::Superellipse(p)),
+            ("ellipse", Some(ratio)) if ratio > 0.0 && ratio <= 1.0 => Ok(Self::Ellipse(ratio)),
+            _ => Err(()),
+        }
+    }
+}
+
+/// The points whose angle from the x axis falls in `start..end` degrees, within `0..=90`.
+///
+/// Each bound is tested as which side of its ray a point lies on, with the ray's direction
+/// rounded to f32 once, so on the start ray is in and on the end ray is out whatever the
+/// rounding. That's what makes disjoint sectors add up to exactly the full count. The y
+/// axis has nothing after it and belongs to whichever sector ends at 90.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Sector {
+    pub start: f64,
+    pub end: f64,
+}
+
+impl Sector {
+    pub const FULL: Self = Self {
+        start: 0.0,
+        end: 90.0,
+    };
+
+    /// The part of the quarter's area the sector holds, the circle being even in angle.
+    pub fn share(self) -> f64 {
+        (self.end - self.start) / (Self::FULL.end - Self::FULL.start)
+    }
+
+    /// The sectors covering the rest of the quarter, which add up to the full count
+    /// together with this one.
+    pub fn complement(self) -> Vec<Self> {
+        let mut complement = Vec::new();
+        if self.start > Self::FULL.start {
+            complement.push(Self {
+                start: Self::FULL.start,
+                end: self.start,
+            });
+        }
+        if self.end < Self::FULL.end {
+            complement.push(Self {
+                start: self.end,
+                end: Self::FULL.end,
+            });
+        }
+        complement
+    }
+
+    /// The shader's bit flags for which bounds to test, 1 for the start and 2 for the end,
+    /// and then the two rays' directions. A bound at the edge of the quarter cuts nothing
+    /// off, so it's left untested.
+    fn uniforms(self) -> [u32; 5] {
+        let direction = |degrees: f64| {
+            let (sin, cos) = degrees.to_radians().sin_cos();
+            [(cos as f32).to_bits(), (sin as f32).to_bits()]
+        };
+        let [start_x, start_y] = direction(self.start);
+        let [end_x, end_y] = direction(self.end);
+
+        let flags =
+            (self.start > Self::FULL.start) as u32 | ((self.end < Self::FULL.end) as u32) << 1;
+        [flags, start_x, start_y, end_x, end_y]
+    }
+}
+
+impl FromStr for Sector {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (start, end) = s.split_once(':').ok_or(())?;
+        let start = start.parse::<f64>().map_err(|_| ())?;
+        let end = end.parse::<f64>().map_err(|_| ())?;
+
+        if Self::FULL.start <= start && start < end && end <= Self::FULL.end {
+            Ok(Self { start, end })
+        } else {
+            Err(())
+        }
+    }
+}
+
+/// How far to move the points of some of the squares off the lattice, for studying how
+/// much the count depends on where exactly the points sit. Each square's points all move
+/// by the same `[dx, dy]`, both in `0..1`, so none of them leaves its unit cell and a
+/// square entirely outside the region stays outside.
+///
+/// Read from text with one square per line, `x y dx dy`, where `[x, y]` is the square's
+/// place in the [`DIVISIONS`] x [`DIVISIONS`] grid. Blank lines and lines starting with `#`
+/// are skipped, and squares that aren't listed aren't moved.
+#[derive(Clone, Default, PartialEq, Debug)]
+pub struct Jitter {
+    squares: HashMap<[u32; 2], [f32; 2]>,
+}
+
+impl Jitter {
+    /// How far the square at `[x, y]` in the grid is moved, if it's listed.
+    pub fn get(&self, square: [u32; 2]) -> Option<[f32; 2]> {
+        self.squares.get(&square).copied()
+    }
+
+    /// The same squares moved by nothing, which must count exactly what the lattice does.
+    pub fn zeroed(&self) -> Self {
+        Self {
+            squares: self
+                .squares
+                .keys()
+                .map(|&square| (square, [0.0; 2]))
+                .collect(),
+        }
+    }
+}
+
+impl FromStr for Jitter {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut squares = HashMap::new();
+
+        for line in s.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let fields = line.split_whitespace().collect::<Vec<_>>();
+            let [x, y, dx, dy] = fields[..] else {
+                return Err(());
+            };
+            let square = [x.parse().map_err(|_| ())?, y.parse().map_err(|_| ())?];
+            let jitter = [dx.parse().map_err(|_| ())?, dy.parse().map_err(|_| ())?];
+
+            let in_grid = square.iter().all(|&i| i < DIVISIONS);
+            let in_cell = jitter.iter().all(|d: &f32| (0.0..1.0).contains(d));
+            if !in_grid || !in_cell || squares.insert(square, jitter).is_some() {
+                return Err(());
+            }
+        }
+
+        Ok(Self { squares })
+    }
+}
+
+/// Lanczos approximation of the gamma function for `x >= 0.5`, good to about 15 digits.
+fn gamma(x: f64) -> f64 {
+    const G: f64 = 7.0;
+    const COEFFICIENTS: [f64; 9] = [
+        0.999_999_999_999_809_9,
+        676.520_368_121_885_1,
+        -1_259.139_216_722_402_8,
+        771.323_428_777_653_1,
+        -176.615_029_162_140_6,
+        12.507_343_278_686_905,
+        -0.138_571_095_265_720_12,
+        9.984_369_578_019_572e-6,
+        1.505_632_735_149_311_6e-7,
+    ];
+
+    let x = x - 1.0;
+    let t = x + G + 0.5;
+    let series = COEFFICIENTS[1..]
+        .iter()
+        .enumerate()
+        .fold(COEFFICIENTS[0], |sum, (i, c)| {
+            sum + c / (x + i as f64 + 1.0)
+        });
+
+    (2.0 * PI).sqrt() * t.powf(x + 0.5) * (-t).exp() * series
+}
+
+/// What the `distances` entry point writes for points outside the region.
+const OUTSIDE: u32 = u32::MAX;
+
+/// Fills every storage buffer past its square's results, to catch the shader writing there.
+const CANARY: u32 = 0xc0ffee42;
+
+/// Adapters without [`wgpu::DownlevelFlags::BUFFER_BINDINGS_NOT_16_BYTE_ALIGNED`] only bind
+/// buffers in multiples of this many bytes.
+const BINDING_ALIGNMENT: u64 = 16;
+
+/// Bytes needed to hold `words` u32s and at least one word of canary after them, rounded
+/// up so the whole buffer can be copied into, mapped and bound everywhere.
+fn buffer_size(words: u32) -> u64 {
+    wgpu::util::align_to(
+        ((words as usize + 1) * size_of::<u32>()) as u64,
+        wgpu::MAP_ALIGNMENT
+            .max(wgpu::COPY_BUFFER_ALIGNMENT)
+            .max(BINDING_ALIGNMENT),
+    )
+}
+
+/// Cuts a storage buffer of `size` bytes into at most `ranges` parts to read back one
+/// after another, each a whole number of mappable words.
+fn read_ranges(size: u64, ranges: u32) -> Vec<Range<BufferAddress>> {
+    assert!(ranges > 0, "Can't Read Back In No Pieces");
+    let piece = wgpu::util::align_to(size.div_ceil(ranges as u64), wgpu::MAP_ALIGNMENT);
+
+    (0..size)
+        .step_by(piece as usize)
+        .map(|start| start..(start + piece).min(size))
+        .collect()
+}
+
+/// How much of a square lies inside the region.
+enum Coverage {
+    Full,
+    Boundary,
+    Outside,
+}
+
+/// Lattice points are counted when `x^2 + y^2 < r^2`. Since the left hand side is an
+/// integer, that is the same as `x^2 + y^2 < ceil(r^2)`, which lets the shader stay in
+/// integer math. `r^2` is exact in an f64 for any f32 `r`, and fits a u32 below
+/// [`MAX_SIZE`].
+pub fn radius_squared_bound(radius: f32) -> u32 {
+    assert!(
+        radius < MAX_SIZE as f32,
+        "Radius {} Squared Doesn't Fit A u32",
+        radius
+    );
+    (radius as f64 * radius as f64).ceil() as u32
+}
+
+/// The size the command line counts when it isn't given one.
+pub const DEFAULT_SIZE: usize = 1024;
+
+/// The largest grid the GPU counts exactly. Its squares' points all lie below 2^16 along
+/// each axis, so `x^2` and `y^2` each fit the u32 the shader compares them in, and the
+/// radius has to stay below it for `r^2` to fit too.
+pub const MAX_SIZE: usize = 1 << 16;
+
+/// Squares along each side of the grid, each either added up whole or sent to the GPU.
+pub const DIVISIONS: u32 = 8;
+
+/// Points along each side of a boundary square that [`Lattice::fast_count`] looks at.
+/// Every square's side is a multiple of 16.
+pub const FAST_SAMPLES: u64 = 16;
+
+/// Lanes the occupancy estimate assumes the GPU runs in lockstep, as NVIDIA's warps and
+/// AMD's RDNA waves do. wgpu doesn't tell us the adapter's own.
+pub const WAVE_WIDTH: u32 = 32;
+
+/// How well a count's dispatches fit the adapter in theory, worked out from its limits and
+/// the readback's workgroups without running anything.
+///
+/// A workgroup of `n = x * y` invocations is split into `ceil(n / WAVE_WIDTH)` waves, and
+/// `wave_fill = n / (WAVE_WIDTH * ceil(n / WAVE_WIDTH))` of their lanes run an invocation at
+/// all. Of the threads dispatched for a square, `busy` have points to count, the rest
+/// returning straight away. The occupancy is `wave_fill * busy`, the share of lanes doing
+/// useful work. `workgroup_share = n / max_compute_invocations_per_workgroup` isn't part
+/// of it, but a small one leaves room for bigger workgroups.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Occupancy {
+    pub workgroup_size: [u32; 2],
+    pub invocations: u32,
+    pub max_invocations: u32,
+    pub workgroup_share: f64,
+    pub waves: u32,
+    pub wave_fill: f64,
+    pub busy: f64,
+    /// Workgroups along x and y for each square, and the most the adapter takes along one.
+    pub dispatch_dims: [u32; 2],
+    pub max_dispatch_dim: u32,
+    /// Squares a count sends to the GPU.
+    pub squares: usize,
+    pub occupancy: f64,
+}
+
+/// How the squares of a grid split up, out of `divisions^2`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CellBreakdown {
+    /// Entirely inside, so counted without the GPU.
+    pub full: u32,
+    /// Cut by the boundary, so dispatched.
+    pub boundary: u32,
+    /// Entirely outside, so skipped.
+    pub outside: u32,
+}
+
+/// What [`estimate`] comes up with for a lattice.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PiEstimate {
+    /// Points counted strictly inside the circle.
+    pub count: u64,
+    pub estimate: f64,
+    /// The estimate exactly, in lowest terms.
+    pub fraction: Fraction,
+}
+
+/// Counts the default circle of [`Lattice::new`] on the best adapter there is, setting it
+/// up first, for a single estimate without the command line. For more than one count it's
+/// cheaper to set up a [`Gpu`] once and call [`Lattice::count`] on it. Fails with
+/// [`PiError::BadSize`] for a size below 2 or above [`MAX_SIZE`] before looking for an
+/// adapter.
+pub async fn estimate(size: usize) -> Result<PiEstimate, PiError> {
+    if !(2..=MAX_SIZE).contains(&size) {
+        return Err(PiError::BadSize { size });
+    }
+
+    let gpu = Gpu::acquire(Features::empty(), gpu::DEFAULT_RETRIES).await?;
+    let lattice = Lattice::new(size);
+    let count = lattice.count(&gpu)?;
+
+    Ok(PiEstimate {
+        count,
+        estimate: lattice.estimate(count),
+        fraction: lattice.fraction(count).unwrap().reduced(),
+    })
+}
+
+/// [`estimate`] for callers that aren't async, blocking the thread until it's done. Not to
+/// be called from inside an async runtime, whose thread it would hold up.
+pub fn estimate_blocking(size: usize) -> Result<PiEstimate, PiError> {
+    pollster::block_on(estimate(size))
+}
+
+/// How the default circle of [`Lattice::new`] splits into full, boundary and outside
+/// squares when the grid is divided `divisions` times along each side, without touching
+/// the GPU. The lattice itself always uses [`DIVISIONS`].
+pub fn cell_breakdown(size: usize, divisions: u32) -> CellBreakdown {
+    let lattice = Lattice::new(size);
+    let side = square_side(size, divisions);
+
+    let mut breakdown = CellBreakdown {
+        full: 0,
+        boundary: 0,
+        outside: 0,
+    };
+    for x in 0..divisions {
+        for y in 0..divisions {
+            match lattice.coverage([x * side, y * side], side) {
+                Coverage::Full => breakdown.full += 1,
+                Coverage::Boundary => breakdown.boundary += 1,
+                Coverage::Outside => breakdown.outside += 1,
+            }
+        }
+    }
+
+    breakdown
+}
+
+/// Each square is rounded up to whole workgroups. That only ever grows the grid past
+/// `size`, and since `radius <= size` the extra points are all outside.
+fn square_side(size: usize, divisions: u32) -> u32 {
+    (size as u32).div_ceil(divisions).next_multiple_of(16)
+}
+
+/// Counts lattice points of a `size` x `size` grid in the first quadrant, with the
+/// origin at one corner.
+#[derive(Clone, Copy, Debug)]
+pub struct Lattice {
+    pub size: usize,
+    /// `size - 1` by default.
+    pub radius: f32,
+    pub readback: Readback,
+    pub predicate: Predicate,
+    /// Only count the points in this sector.
+    pub sector: Option<Sector>,
+    /// Significant bits of the radius the GPU gets, all 24 of an f32 by default. Fewer
+    /// truncate it, to see how much the count leans on the radius being exact.
+    pub radius_bits: u32,
+    /// Map the storage buffers themselves instead of copying them into separate read
+    /// buffers first, to tell a bad copy from a bad count. Only where the device was set up
+    /// with [`Features::MAPPABLE_PRIMARY_BUFFERS`], and ignored elsewhere.
+    pub map_storage: bool,
+    /// Pieces to copy and map each square's results back in when counting, each piece of
+    /// every square going off in a submission of its own. The host adds up the first
+    /// pieces while the GPU is still copying the rest, instead of waiting on whole squares,
+    /// which makes the wait for the first results of a large square shorter. 1 by default,
+    /// and ignored when mapping the storage buffers.
+    pub ranges: u32,
+    /// Count the points on the circle too, `x^2 + y^2 <= r^2`, where normally they're left
+    /// out. Only for the circle, which the GPU counts exactly, and only on the lattice, so
+    /// the radius has to fall short of the grid's edge for the axes' last points to be in
+    /// it.
+    pub inclusive: bool,
+    /// Which quadrant of the plane to count in, anticlockwise from the first. Each square
+    /// is mirrored into it and the GPU tests the points where they land, so all four count
+    /// the same unless the shader or the mirrored offsets are off.
+    pub quadrant: u32,
+    /// How many points too far out the squares of the quadrants left of or below the first
+    /// are mirrored, as an off by one in the mirroring would put them, to show the quadrant
+    /// counts catching it.
+    #[cfg(test)]
+    mirror_slip: i32,
+}
+
+impl Lattice {
+    pub fn new(size: usize) -> Self {
+        Self {
+            size,
+            radius: (size - 1) as f32,
+            readback: Readback::Grid,
+            predicate: Predicate::Circle,
+            sector: None,
+            radius_bits: f32::MANTISSA_DIGITS,
+            map_storage: false,
+            ranges: 1,
+            inclusive: false,
+            quadrant: 0,
+            #[cfg(test)]
+            mirror_slip: 0,
+        }
+    }
+
+    /// Which way each axis points in `quadrant`.
+    fn quadrant_signs(&self) -> [i32; 2] {
+        match self.quadrant {
+            0 => [1, 1],
+            1 => [-1, 1],
+            2 => [-1, -1],
+            3 => [1, -1],
+            _ => panic!("No Quadrant {}", self.quadrant),
+        }
+    }
+
+    /// The corner nearest the origin of the square at `offset`, mirrored into `quadrant`.
+    fn mirror(&self, offset: [u32; 2]) -> [i32; 2] {
+        let signs = self.quadrant_signs();
+        let mirrored = [0, 1].map(|axis| offset[axis] as i32 * signs[axis]);
+
+        #[cfg(test)]
+        let mirrored =
+            [0, 1].map(|axis| mirrored[axis] - (signs[axis] < 0) as i32 * self.mirror_slip);
+
+        mirrored
+    }
+
+    /// The area of the region actually counted, before the factor of 4: the predicate's
+    /// normalizer cut down to the sector's share. Every estimate divides by this, so a new
+    /// kind of region only has to get its area right here.
+    pub fn normalizer(&self) -> f64 {
+        self.predicate.normalizer(self.radius) * self.sector.map_or(1.0, Sector::share)
+    }
+
+    /// `4 * count / normalizer`, which converges to the predicate's constant.
+    pub fn estimate(&self, count: u64) -> f64 {
+        count as f64 * 4.0 / self.normalizer()
+    }
+
+    /// The estimate as an exact fraction, for the regions whose normalizer is a whole
+    /// number: the circle of a whole radius, without a sector.
+    pub fn fraction(&self, count: u64) -> Option<Fraction> {
+        let fraction = match self.predicate {
+            Predicate::Circle if self.radius.fract() == 0.0 && self.sector.is_none() => {
+                let radius = self.radius as u64;
+                Fraction::new(count * 4, radius * radius)
+            }
+            _ => return None,
+        };
+
+        assert!(
+            fraction.denominator as f64 == self.normalizer(),
+            "Fraction Denominator {} Isn't The Region's Area {}",
+            fraction.denominator,
+            self.normalizer()
+        );
+        Some(fraction)
+    }
+
+    /// The radius truncated to `radius_bits`, which is what the points are actually
+    /// counted against.
+    pub fn counted_radius(&self) -> f32 {
+        assert!(
+            (1..=f32::MANTISSA_DIGITS).contains(&self.radius_bits),
+            "Radius Bits Out Of Range"
+        );
+
+        let dropped = f32::MANTISSA_DIGITS - self.radius_bits;
+        f32::from_bits(self.radius.to_bits() & !((1 << dropped) - 1))
+    }
+
+    /// Whether the point is counted against `radius`: inside the region, or on the circle
+    /// when counting inclusively. `r^2` and `x^2 + y^2` are exact in an f64.
+    fn counts(&self, radius: f32, x: u64, y: u64) -> bool {
+        self.predicate.contains(radius, x, y)
+            || (self.inclusive && (x * x + y * y) as f64 == radius as f64 * radius as f64)
+    }
+
+    /// Every region is monotone in `x` and `y`, so a square is entirely inside when its
+    /// far corner is and entirely outside when its near corner is.
+    fn coverage(&self, offset: [u32; 2], side: u32) -> Coverage {
+        let radius = self.counted_radius();
+        let contains = |x: u32, y: u32| self.counts(radius, x as u64, y as u64);
+        let [x, y] = offset;
+
+        if contains(x + side - 1, y + side - 1) {
+            Coverage::Full
+        } else if contains(x, y) {
+            Coverage::Boundary
+        } else {
+            Coverage::Outside
+        }
+    }
+
+    fn side(&self) -> u32 {
+        square_side(self.size, DIVISIONS)
+    }
+
+    /// Sorts the squares into those needing the GPU and those that don't. With `dense` set
+    /// the full squares are dispatched too, for when the per point data is wanted. A sector
+    /// can cut through a full square, so it always needs them dispatched, and so can
+    /// `jitter` through the full squares it moves.
+    fn plan(&self, dense: bool, jitter: Option<&Jitter>) -> Plan {
+        assert!(self.radius > 0.0, "Radius Must Be Positive");
+        assert!(self.radius <= self.size as f32, "Radius Larger Than Grid");
+        assert!(self.size <= MAX_SIZE, "Grid Larger Than {}", MAX_SIZE);
+        assert!(
+            !self.inclusive || (self.predicate == Predicate::Circle && jitter.is_none()),
+            "Only The Circle's Lattice Can Be Counted Inclusively"
+        );
+        assert!(
+            !self.inclusive || self.radius < self.size as f32,
+            "Inclusive Radius Reaches Past The Grid"
+        );
+
+        let s = self.side();
+        let full = s as u64 * s as u64;
+
+        // The circle and superellipse don't tell x from y, so for them the square at [y, x]
+        // holds exactly as many points as the one at [x, y], and only those on or below the
+        // diagonal need looking at.
+        // A sector or a jitter tells them apart too.
+        let symmetric = matches!(
+            self.predicate,
+            Predicate::Circle | Predicate::Superellipse(_)
+        ) && self.sector.is_none()
+            && jitter.is_none();
+        let dense = dense || self.sector.is_some();
+
+        let mut plan = Plan {
+            full: 0,
+            squares: Vec::new(),
+        };
+        for x in 0..DIVISIONS {
+            for y in 0..DIVISIONS {
+                let weight = match (symmetric, x.cmp(&y)) {
+                    (false, _) | (true, Ordering::Equal) => 1,
+                    (true, Ordering::Greater) => 2,
+                    (true, Ordering::Less) => continue,
+                };
+                let offset = [x * s, y * s];
+                let moved = jitter.is_some_and(|jitter| jitter.get([x, y]).is_some());
+
+                match self.coverage(offset, s) {
+                    Coverage::Full if !dense && !moved => plan.full += weight * full,
+                    Coverage::Full | Coverage::Boundary => plan.squares.push((weight, offset)),
+                    Coverage::Outside => {}
+                }
+            }
+        }
+
+        plan
+    }
+
+    /// Runs `entry_point` over each of the plan's squares, laying results out as
+    /// `readback` does, and waits until every square is mapped for reading. Fails if any
+    /// square was written past its end.
+    fn dispatch(
+        &self,
+        gpu: &Gpu,
+        plan: &Plan,
+        entry_point: &str,
+        readback: Readback,
+    ) -> Result<Vec<Square>, PiError> {
+        let (squares, submission) = self.submit(gpu, plan, entry_point, readback, None, 1);
+        for square in &squares {
+            square.map();
+        }
+        gpu.wait(submission);
+
+        for square in &squares {
+            square.check_canary()?;
+        }
+
+        Ok(squares)
+    }
+
+    /// Sends the plan's squares off to the GPU without waiting for them, in plan order,
+    /// copying their results back in `ranges` pieces. Returns the last submission.
+    fn submit(
+        &self,
+        gpu: &Gpu,
+        plan: &Plan,
+        entry_point: &str,
+        readback: Readback,
+        jitter: Option<&Jitter>,
+        ranges: u32,
+    ) -> (Vec<Square>, SubmissionIndex) {
+        let s = self.side();
+        let radius = self.counted_radius();
+        let radius_squared = match self.inclusive {
+            // x^2 + y^2 <= r^2 is x^2 + y^2 < floor(r^2) + 1 in integers
+            true => (radius as f64 * radius as f64).floor() as u32 + 1,
+            false => radius_squared_bound(radius),
+        };
+
+        let shader = gpu
+            .device
+            .create_shader_module(include_wgsl!("compute.wgsl"));
+
+        let map_storage = self.map_storage
+            && gpu
+                .device
+                .features()
+                .contains(Features::MAPPABLE_PRIMARY_BUFFERS);
+
+        let storage_buffer_descriptor = BufferDescriptor {
+            label: Some("Storage Buffer"),
+            size: buffer_size(readback.words(s)),
+            usage: match map_storage {
+                true => BufferUsages::STORAGE | BufferUsages::COPY_SRC | BufferUsages::MAP_READ,
+                false => BufferUsages::STORAGE | BufferUsages::COPY_SRC,
+            },
+            // to fill in the canary
+            mapped_at_creation: true,
+        };
+
+        let read_ranges = match map_storage {
+            true => Vec::new(),
+            false => read_ranges(storage_buffer_descriptor.size, ranges),
+        };
+
+        let [sector, start_x, start_y, end_x, end_y] =
+            self.sector.unwrap_or(Sector::FULL).uniforms();
+        let [sign_x, sign_y] = self.quadrant_signs();
+        let options = [
+            radius_squared,
+            s,
+            self.predicate.id(),
+            self.predicate.parameter().to_bits(),
+            radius.to_bits(),
+            sector,
+            start_x,
+            start_y,
+            end_x,
+            end_y,
+            sign_x as u32,
+            sign_y as u32,
+        ];
+
+        let options_buffer = gpu
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Size Buffer"),
+                contents: bytemuck::cast_slice(&options),
+                usage: BufferUsages::UNIFORM,
+            });
+
+        let compute_pipeline =
+            gpu.device
+                .create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                    label: None,
+                    layout: None,
+                    module: &shader,
+                    entry_point,
+                });
+
+        let bind_group = gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &compute_pipeline.get_bind_group_layout(0),
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: options_buffer.as_entire_binding(),
+            }],
+        });
+
+        let offset_bind_group_layout = compute_pipeline.get_bind_group_layout(1);
+
+        let common_opts = SquareCommonOptions {
+            device: &gpu.device,
+            side: s,
+            readback,
+            storage_buffer_descriptor: &storage_buffer_descriptor,
+            read_ranges: &read_ranges,
+            bind_group_layout: &offset_bind_group_layout,
+        };
+
+        let squares = plan
+            .squares
+            .iter()
+            .map(|&(_, offset)| {
+                let moved = jitter.and_then(|jitter| jitter.get([offset[0] / s, offset[1] / s]));
+                Square::new(
+                    offset,
+                    self.mirror(offset),
+                    moved.unwrap_or_default(),
+                    &common_opts,
+                )
+            })
+            .collect::<Vec<_>>();
+
+        let mut encoder = gpu
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        {
+            let mut cpass =
+                encoder.begin_compute_pass(&wgpu::ComputePassDescriptor { label: None });
+            cpass.set_pipeline(&compute_pipeline);
+            cpass.set_bind_group(0, &bind_group, &[]);
+
+            for square in &squares {
+                square.compute(&mut cpass);
+            }
+        }
+
+        // the first piece goes with the dispatches, and each after it by itself so its map
+        // callbacks can run while the next is still being copied
+        for square in &squares {
+            square.copy(&mut encoder, 0);
+        }
+        let mut submission = gpu.queue.submit(Some(encoder.finish()));
+        for piece in 1..read_ranges.len() {
+            let mut encoder = gpu
+                .device
+                .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+            for square in &squares {
+                square.copy(&mut encoder, piece);
+            }
+            submission = gpu.queue.submit(Some(encoder.finish()));
+        }
+
+        (squares, submission)
+    }
+
+    /// How well [`Lattice::count`]'s dispatches would fit an adapter with `limits`, as
+    /// [`Occupancy`] describes.
+    pub fn occupancy(&self, limits: &Limits) -> Occupancy {
+        let s = self.side();
+        let readback = self.readback;
+
+        let workgroup_size = readback.workgroup_size();
+        let invocations = workgroup_size[0] * workgroup_size[1];
+        let waves = invocations.div_ceil(WAVE_WIDTH);
+        let wave_fill = invocations as f64 / (waves * WAVE_WIDTH) as f64;
+
+        let threads = readback.threads(s);
+        let cells_per_thread = readback.cells_per_thread(s);
+        let busy = [0, 1]
+            .map(|axis| s.div_ceil(cells_per_thread[axis]) as f64 / threads[axis] as f64)
+            .iter()
+            .product::<f64>();
+
+        Occupancy {
+            workgroup_size,
+            invocations,
+            max_invocations: limits.max_compute_invocations_per_workgroup,
+            workgroup_share: invocations as f64
+                / limits.max_compute_invocations_per_workgroup as f64,
+            waves,
+            wave_fill,
+            busy,
+            dispatch_dims: readback.dispatch_dims(s),
+            max_dispatch_dim: limits.max_compute_workgroups_per_dimension,
+            squares: self.plan(false, None).squares.len(),
+            occupancy: wave_fill * busy,
+        }
+    }
+
+    /// A rough count without the GPU: the full squares' points exactly, and every square
+    /// the boundary cuts through scaled up from [`FAST_SAMPLES`] x [`FAST_SAMPLES`] of its
+    /// points spread evenly across it. Not for sectors, which cut through full squares too.
+    ///
+    /// The samples are the same handful of points whatever the size, so the estimate's
+    /// error stays around `1e-3` for the circle rather than shrinking like `1/size` as the
+    /// full count's does: about as close at the default size of 1024, and several times
+    /// further off than the full count's `2.4e-4` by 16384. Up to a size of 128 every point
+    /// of a square is a sample and the count is exact.
+    pub fn fast_count(&self) -> u64 {
+        assert!(self.sector.is_none(), "Fast Count Can't Cut Out A Sector");
+
+        let s = self.side() as u64;
+        let radius = self.counted_radius();
+        let step = s / FAST_SAMPLES;
+        let plan = self.plan(false, None);
+
+        let boundary = plan
+            .squares
+            .iter()
+            .map(|&(weight, [x, y])| {
+                let samples = (0..FAST_SAMPLES * FAST_SAMPLES)
+                    .filter(|i| {
+                        let x = x as u64 + i % FAST_SAMPLES * step + step / 2;
+                        let y = y as u64 + i / FAST_SAMPLES * step + step / 2;
+                        self.predicate.contains(radius, x, y)
+                    })
+                    .count() as u64;
+                weight * samples * step * step
+            })
+            .sum::<u64>();
+
+        plan.full + boundary
+    }
+
+    /// Counts the lattice points of the grid that fall strictly inside the region.
+    pub fn count(&self, gpu: &Gpu) -> Result<u64, PiError> {
+        self.count_with(gpu, |_, _, _| {})
+    }
+
+    /// Like [`Lattice::count`], calling `on_square([x, y], weight, count)` with the count of
+    /// each square that went to the GPU once they're all in. `[x, y]` is the square's place in
+    /// the 8 x 8 grid and `weight` how many squares it stands in for, so the weighted counts
+    /// add up to the total less the full squares, which never reach the GPU.
+    pub fn count_with(
+        &self,
+        gpu: &Gpu,
+        on_square: impl FnMut([u32; 2], u64, u32),
+    ) -> Result<u64, PiError> {
+        self.total(gpu, false, None, on_square)
+    }
+
+    /// Like [`Lattice::count`], with the squares `jitter` lists counted at their moved
+    /// points. Moved points of the circle are tested in f32, as every other region's are,
+    /// rather than in the exact integer math of the lattice.
+    pub fn count_jittered(&self, gpu: &Gpu, jitter: &Jitter) -> Result<u64, PiError> {
+        self.total(gpu, false, Some(jitter), |_, _, _| {})
+    }
+
+    /// Counts each quadrant of the plane by itself, sending even the full squares to the
+    /// GPU so every point goes through the reflection. They should all be the same.
+    pub fn quadrant_counts(&self, gpu: &Gpu) -> Result<[u64; 4], PiError> {
+        let mut counts = [0; 4];
+        for (quadrant, count) in counts.iter_mut().enumerate() {
+            let lattice = Self {
+                quadrant: quadrant as u32,
+                ..*self
+            };
+            *count = lattice.total(gpu, true, None, |_, _, _| {})?;
+        }
+        Ok(counts)
+    }
+
+    fn total(
+        &self,
+        gpu: &Gpu,
+        dense: bool,
+        jitter: Option<&Jitter>,
+        mut on_square: impl FnMut([u32; 2], u64, u32),
+    ) -> Result<u64, PiError> {
+        let plan = self.plan(dense, jitter);
+        let (squares, submission) = self.submit(
+            gpu,
+            &plan,
+            self.readback.entry_point(),
+            self.readback,
+            jitter,
+            self.ranges,
+        );
+
+        // each piece of each square is added up and let go by its own map callback as soon
+        // as it's ready, rather than all of them being kept until the last one is
+        let accumulator = Arc::new(Mutex::new(Accumulator::default()));
+        for (i, (&(weight, _), square)) in plan.squares.iter().zip(squares).enumerate() {
+            let square = Arc::new(square);
+            for (piece, (start, buffer)) in square.pieces().into_iter().enumerate() {
+                let mapped = Arc::clone(&square);
+                let accumulator = Arc::clone(&accumulator);
+
+                buffer.slice(..).map_async(MapMode::Read, move |e| {
+                    e.unwrap();
+                    let buffer = mapped.pieces()[piece].1;
+                    let counted =
+                        mapped.tally(start, mapped::words(&buffer.slice(..).get_mapped_range()));
+                    buffer.unmap();
+
+                    let mut accumulator = accumulator.lock().unwrap();
+                    match counted {
+                        Ok(count) => {
+                            accumulator.total += weight * count as u64;
+                            accumulator.counts.push((i, count));
+                        }
+                        Err(error) => {
+                            accumulator.error.get_or_insert(error);
+                        }
+                    }
+                });
+            }
+        }
+        gpu.wait(submission);
+
+        let mut accumulator = accumulator.lock().unwrap();
+        if let Some(error) = accumulator.error.take() {
+            return Err(error);
+        }
+
+        accumulator.counts.sort_unstable();
+        let s = self.side();
+        for pieces in accumulator.counts.chunk_by(|a, b| a.0 == b.0) {
+            let (weight, [x, y]) = plan.squares[pieces[0].0];
+            let count = pieces.iter().map(|&(_, count)| count).sum();
+            on_square([x / s, y / s], weight, count);
+        }
+
+        Ok(plan.full + accumulator.total)
+    }
+
+    /// Fetches the grid of every square and checks each of its points was counted exactly
+    /// when the region holds the point at the square's offset, returning the first one that
+    /// wasn't. A square bound to the wrong offset still counts a plausible number of points
+    /// from somewhere else, which no total can give away. The sector is left out, since the
+    /// CPU can't round its rays the way the GPU does.
+    pub fn check_offsets(&self, gpu: &Gpu) -> Result<(), PiError> {
+        let lattice = Self {
+            sector: None,
+            ..*self
+        };
+        let plan = lattice.plan(true, None);
+        let squares = lattice.dispatch(gpu, &plan, Readback::Grid.entry_point(), Readback::Grid)?;
+
+        // only the circle is counted exactly, the rest to within f32 rounding
+        let tolerance = match self.predicate {
+            Predicate::Circle => 0.0,
+            _ => 1e-5,
+        };
+        let contains = |radius: f32, [x, y]: [u32; 2]| self.counts(radius, x as u64, y as u64);
+
+        let radius = self.counted_radius();
+        let s = self.side();
+        for ((_, offset), square) in plan.squares.iter().zip(&squares) {
+            for (i, &counted) in square.cells().iter().enumerate() {
+                let point = [offset[0] + i as u32 % s, offset[1] + i as u32 / s];
+
+                let surely_in = contains(radius * (1.0 - tolerance), point);
+                let maybe_in = contains(radius * (1.0 + tolerance), point);
+                if (counted == 1 && !maybe_in) || (counted == 0 && surely_in) {
+                    return Err(PiError::OffsetMismatch { point });
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Bins every counted point by its distance from the origin into `bins` equal bins
+    /// spanning `0..predicate.extent(radius)`. The bins add up to [`Lattice::count`].
+    pub fn distance_histogram(&self, gpu: &Gpu, bins: usize) -> Result<Vec<u64>, PiError> {
+        let plan = self.plan(true, None);
+        let squares = self.dispatch(gpu, &plan, "distances", Readback::Grid)?;
+
+        let bin_width = self.predicate.extent(self.counted_radius()) / bins as f64;
+
+        let mut histogram = vec![0; bins];
+        for ((weight, _), square) in plan.squares.iter().zip(&squares) {
+            for distance in square.cells() {
+                if distance != OUTSIDE {
+                    let bin = ((distance as f64).sqrt() / bin_width) as usize;
+                    histogram[bin.min(bins - 1)] += weight;
+                }
+            }
+        }
+
+        Ok(histogram)
+    }
+}
+
+/// Where the squares of a count are added up as their map callbacks come in, from whichever
+/// thread happens to be polling the device.
+#[derive(Default)]
+struct Accumulator {
+    total: u64,
+    /// Each square's place in the plan with the count of one of its pieces, in the order
+    /// they came in.
+    counts: Vec<(usize, u32)>,
+    /// The first square found written past its end.
+    error: Option<PiError>,
+}
+
+/// The squares of the grid that need the GPU, and how many points the rest hold.
+struct Plan {
+    /// Points in the full squares, added up without the GPU.
+    full: u64,
+    /// The squares to dispatch, each with how many squares it stands in for.
+    squares: Vec<(u64, [u32; 2])>,
+}
+
+#[cfg(test)]
+mod tests {
+    use wgpu::Features;
+
+    use super::*;
+    use crate::cpu;
+
+    fn gpu() -> Gpu {
+        Gpu::acquire_blocking(Features::empty(), 0).expect("No GPU Adapter For Tests")
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter"]
+    fn fractional_radii_match_cpu() {
+        let gpu = gpu();
+
+        for radius in [100.5, 200.25, 255.999, 300.1, 511.75] {
+            let lattice = Lattice {
+                radius,
+                ..Lattice::new(512)
+            };
+            assert_eq!(
+                lattice.count(&gpu).unwrap(),
+                cpu::lattice_count(Predicate::Circle, radius),
+                "radius {}",
+                radius
+            );
+        }
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter"]
+    fn float_predicates_match_cpu() {
+        let gpu = gpu();
+
+        let predicates = [
+            Predicate::Superellipse(1.0),
+            Predicate::Superellipse(4.0),
+            Predicate::Ellipse(0.5),
+            Predicate::Sqrt,
+        ];
+        for predicate in predicates {
+            for radius in [100.5, 511.0, 999.0] {
+                let lattice = Lattice {
+                    predicate,
+                    radius,
+                    ..Lattice::new(1000)
+                };
+                let count = lattice.count(&gpu).unwrap();
+                let expected = cpu::lattice_count(predicate, radius);
+                let ambiguous = cpu::ambiguous_count(predicate, radius);
+                assert!(
+                    count.abs_diff(expected) <= ambiguous,
+                    "{:?} at radius {}: {} != {} give or take {}",
+                    predicate,
+                    radius,
+                    count,
+                    expected,
+                    ambiguous
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn dispatch_covers_square() {
+        let readbacks = Readback::ALL.into_iter().chain([Readback::SingleWorkgroup]);
+        for readback in readbacks {
+            for size in (1..20_000).step_by(97).chain([128, 1024, 4096, 16384]) {
+                let side = square_side(size, DIVISIONS);
+                let workgroup_size = readback.workgroup_size();
+                let cells_per_thread = readback.cells_per_thread(side);
+                let threads = readback.threads(side);
+
+                let covered = [0, 1].map(|axis| threads[axis] * cells_per_thread[axis]);
+                match readback {
+                    // the last workgroup along bitpacked's x is cut short by the shader
+                    Readback::Bitpacked => {
+                        let slack = workgroup_size[0] * cells_per_thread[0];
+                        assert!(covered[0] >= side && covered[0] - side < slack);
+                        assert_eq!(covered[1], side);
+                    }
+                    _ => assert_eq!(covered, [side; 2], "{:?} at size {}", readback, size),
+                }
+                readback.check_dispatch(side);
+            }
+        }
+    }
+
+    #[test]
+    fn cell_breakdown_covers_grid() {
+        for divisions in 1..=16 {
+            for size in (2..5000).step_by(41).chain([DEFAULT_SIZE, 65536]) {
+                let breakdown = cell_breakdown(size, divisions);
+                assert_eq!(
+                    breakdown.full + breakdown.boundary + breakdown.outside,
+                    divisions * divisions,
+                    "size {} in {} divisions",
+                    size,
+                    divisions
+                );
+                assert!(breakdown.boundary > 0);
+            }
+        }
+    }
+
+    #[test]
+    fn buffer_sizes_are_aligned() {
+        for words in 1..=300 {
+            let size = buffer_size(words);
+            let raw = (words as u64 + 1) * size_of::<u32>() as u64;
+
+            assert!(size >= raw, "{} words", words);
+            assert_eq!(size % wgpu::COPY_BUFFER_ALIGNMENT, 0, "{} words", words);
+            assert_eq!(size % wgpu::MAP_ALIGNMENT, 0, "{} words", words);
+            assert_eq!(size % BINDING_ALIGNMENT, 0, "{} words", words);
+        }
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter"]
+    fn unaligned_sizes_match_cpu() {
+        let gpu = gpu();
+
+        // a reduce square's one word and canary come to 8 bytes, and 1001 isn't a
+        // multiple of the divisions or the workgroups
+        for readback in Readback::ALL {
+            for size in [17, 100, 1001] {
+                let lattice = Lattice {
+                    readback,
+                    ..Lattice::new(size)
+                };
+                assert_eq!(
+                    lattice.count(&gpu).unwrap(),
+                    cpu::lattice_count(Predicate::Circle, lattice.radius),
+                    "{:?} at size {}",
+                    readback,
+                    size
+                );
+            }
+        }
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter"]
+    fn read_ranges_add_up() {
+        let gpu = gpu();
+
+        // a reduce square's 16 bytes only go into 2 pieces of 8, the second all canary, and
+        // the pieces of the others split rows and end partway into the canary
+        for readback in Readback::ALL {
+            for ranges in [1, 2, 3, 7] {
+                let lattice = Lattice {
+                    readback,
+                    ranges,
+                    ..Lattice::new(1001)
+                };
+                assert_eq!(
+                    lattice.count(&gpu).unwrap(),
+                    cpu::lattice_count(Predicate::Circle, lattice.radius),
+                    "{:?} in {} ranges",
+                    readback,
+                    ranges
+                );
+            }
+        }
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter"]
+    fn large_grids_match_cpu() {
+        let gpu = gpu();
+
+        // the boundary squares' far corners are over 65536 from the origin, where x^2 + y^2
+        // runs past a u32
+        let lattice = Lattice {
+            readback: Readback::Reduce,
+            ..Lattice::new(MAX_SIZE)
+        };
+        assert_eq!(
+            lattice.count(&gpu).unwrap(),
+            cpu::lattice_count(Predicate::Circle, lattice.radius)
+        );
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter"]
+    fn offset_shifts_square() {
+        let gpu = gpu();
+        let lattice = Lattice::new(1024);
+        let s = lattice.side();
+
+        // cut by the circle away from both axes, so an offset dropped or swapped for the
+        // other axis shows up
+        let offset = [6 * s, 4 * s];
+        assert!(matches!(lattice.coverage(offset, s), Coverage::Boundary));
+
+        let plan = Plan {
+            full: 0,
+            squares: vec![(1, offset)],
+        };
+        let squares = lattice
+            .dispatch(&gpu, &plan, Readback::Grid.entry_point(), Readback::Grid)
+            .unwrap();
+
+        for (i, &counted) in squares[0].cells().iter().enumerate() {
+            let [x, y] = [offset[0] + i as u32 % s, offset[1] + i as u32 / s];
+            let inside = Predicate::Circle.contains(lattice.radius, x as u64, y as u64);
+            assert_eq!(counted == 1, inside, "point ({}, {})", x, y);
+        }
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter"]
+    fn over_dispatch_trips_canary() {
+        let gpu = gpu();
+        let lattice = Lattice::new(1024);
+        let plan = lattice.plan(false, None);
+
+        // rows' buffers hold a word per row, and the grid entry point over rows' dispatch
+        // writes a word per point of its first 16 rows, running on into the canary
+        let result = lattice.dispatch(&gpu, &plan, Readback::Grid.entry_point(), Readback::Rows);
+        assert!(
+            matches!(result, Err(PiError::BufferCorruption { .. })),
+            "Canary Survived"
+        );
+
+        let result = lattice.dispatch(&gpu, &plan, Readback::Rows.entry_point(), Readback::Rows);
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter"]
+    fn quadrants_agree() {
+        let gpu = gpu();
+
+        let predicates = [
+            Predicate::Circle,
+            Predicate::Superellipse(4.0),
+            Predicate::Ellipse(0.5),
+            Predicate::Sqrt,
+        ];
+        for predicate in predicates {
+            let lattice = Lattice {
+                predicate,
+                ..Lattice::new(1000)
+            };
+            let counts = lattice.quadrant_counts(&gpu).unwrap();
+            assert_eq!(counts, [counts[0]; 4], "{:?}", predicate);
+
+            // every quadrant but the first gets a column or row of points too few
+            let slipped = Lattice {
+                mirror_slip: 1,
+                ..lattice
+            }
+            .quadrant_counts(&gpu)
+            .unwrap();
+            assert_eq!(slipped[0], counts[0], "{:?}", predicate);
+            assert!(
+                slipped[1..].iter().all(|&x| x < counts[0]),
+                "{:?}",
+                predicate
+            );
+        }
+    }
+
+    #[test]
+    fn estimates_converge() {
+        let predicates = [
+            Predicate::Circle,
+            Predicate::Superellipse(1.0),
+            Predicate::Superellipse(4.0),
+            Predicate::Ellipse(0.5),
+            Predicate::Sqrt,
+        ];
+        for predicate in predicates {
+            let error = |size| {
+                let lattice = Lattice {
+                    predicate,
+                    ..Lattice::new(size)
+                };
+                let count = cpu::lattice_count(predicate, lattice.radius);
+                (lattice.estimate(count) - predicate.constant()).abs()
+            };
+
+            let (coarse, fine) = (error(256), error(4096));
+            assert!(
+                fine < coarse / 4.0,
+                "{:?}: {} then {}",
+                predicate,
+                coarse,
+                fine
+            );
+            assert!(fine < 2e-3, "{:?}: {}", predicate, fine);
+        }
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter"]
+    fn sector_estimate_converges() {
+        let gpu = gpu();
+
+        for (start, end) in [(0.0, 30.0), (10.0, 80.0), (45.0, 90.0)] {
+            let lattice = Lattice {
+                sector: Some(Sector { start, end }),
+                ..Lattice::new(4096)
+            };
+            let error = (lattice.estimate(lattice.count(&gpu).unwrap()) - PI).abs();
+            assert!(error < 2e-3, "{}..{}: {}", start, end, error);
+        }
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter"]
+    fn estimate_blocks() {
+        let estimate = estimate_blocking(1024).unwrap();
+        assert_eq!(estimate.count, 822940);
+        assert_eq!(estimate.fraction, Fraction::new(3291760, 1046529));
+        assert_eq!(estimate.estimate, Fraction::new(3291760, 1046529).to_f64());
+    }
+
+    #[test]
+    fn estimate_rejects_bad_sizes() {
+        for size in [0, 1, MAX_SIZE + 1] {
+            assert_eq!(estimate_blocking(size), Err(PiError::BadSize { size }));
+        }
+    }
+}
@@ -0,0 +1,92 @@
+use wgpu::include_wgsl;
+
+use crate::{
+    gpu::Gpu,
+    reduction::{self, Reduction},
+};
+
+/// Pairs of terms added up by a single dispatch, kept well below `u32::MAX` for the same
+/// reason as Monte Carlo's samples.
+const MAX_DISPATCH_PAIRS: u64 = 1 << 30;
+
+/// Estimates pi from the Leibniz series `4 (1 - 1/3 + 1/5 - 1/7 + ...)`, there to show
+/// how slow a series can be.
+///
+/// After `n` terms the partial sum is off by almost exactly `1/n`, so every digit of pi
+/// costs ten times the terms: a million terms for 6 digits, where the lattice's error
+/// shrinks like `1/size` on `size^2` points and the polygon's like `1/n^2` on `n` sides.
+/// The f32 partials put a floor of about `1e-7` under the error.
+pub struct Leibniz {
+    reduction: Reduction,
+    /// Add the partials up with compensated summation, as [`Polygon`](crate::polygon::Polygon)
+    /// can.
+    pub compensated: bool,
+}
+
+impl Leibniz {
+    pub fn new(gpu: &Gpu) -> Self {
+        Self {
+            reduction: Reduction::new(gpu, include_wgsl!("leibniz.wgsl")),
+            compensated: false,
+        }
+    }
+
+    /// Four times the sum of the series' first `terms` terms.
+    pub fn estimate(&self, gpu: &Gpu, terms: u64) -> f64 {
+        let pairs = terms.div_ceil(2);
+
+        let dispatches = (0..pairs)
+            .step_by(MAX_DISPATCH_PAIRS as usize)
+            .map(|first| {
+                let count = (pairs - first).min(MAX_DISPATCH_PAIRS);
+                let odd = first + count == pairs && terms % 2 == 1;
+                [first as u32, (first >> 32) as u32, count as u32, odd as u32]
+            });
+
+        let sums = self.reduction.run_all(gpu, dispatches, |sums| {
+            let sums: &[f32] = bytemuck::cast_slice(sums);
+            reduction::sum(sums.iter().map(|&x| x as f64), self.compensated)
+        });
+
+        4.0 * reduction::sum(sums, self.compensated)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::PI;
+
+    use wgpu::Features;
+
+    use super::*;
+    use crate::cpu;
+
+    fn gpu() -> Gpu {
+        Gpu::acquire_blocking(Features::empty(), 0).expect("No GPU Adapter For Tests")
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter"]
+    fn estimate_matches_cpu() {
+        let gpu = gpu();
+        let leibniz = Leibniz::new(&gpu);
+
+        // the odd counts end on a pair cut short to its positive term
+        let mut last_error = f64::INFINITY;
+        for terms in [1, 2, 3, 10, 101, 1000, 10001, 100000] {
+            let estimate = leibniz.estimate(&gpu, terms);
+            let expected = cpu::leibniz(terms);
+            assert!(
+                (estimate - expected).abs() <= 1e-6,
+                "{} terms: {} != {}",
+                terms,
+                estimate,
+                expected
+            );
+
+            let error = (estimate - PI).abs();
+            assert!(error < last_error, "{} terms: {}", terms, error);
+            last_error = error;
+        }
+    }
+}
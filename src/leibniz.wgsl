@@ -0,0 +1,37 @@
+struct Options {
+    first_lo: u32,
+    first_hi: u32,
+    count: u32,
+    // 1 when the last pair is cut short and only has its positive term
+    odd: u32,
+}
+
+@group(0) @binding(0)
+var<uniform> options: Options;
+@group(0) @binding(1)
+var<storage, read_write> result: array<f32>;
+
+// Each thread adds up the pairs of terms 1/(4j+1) - 1/(4j+3) it strides over, taken
+// together as 2/((4j+1)(4j+3)) so nothing cancels and every partial is positive
+@compute @workgroup_size(64, 1, 1)
+fn main(
+    @builtin(global_invocation_id) invocation_id: vec3<u32>,
+    @builtin(num_workgroups) num_workgroups: vec3<u32>,
+) {
+    var threads = num_workgroups.x * 64u;
+    var sum = 0.0;
+
+    for (var n = invocation_id.x; n < options.count; n += threads) {
+        var lo = options.first_lo + n;
+        var hi = options.first_hi + u32(lo < n);
+        var a = 4.0 * (f32(hi) * 4294967296.0 + f32(lo)) + 1.0;
+
+        if options.odd == 1u && n == options.count - 1u {
+            sum += 1.0 / a;
+        } else {
+            sum += 2.0 / (a * (a + 2.0));
+        }
+    }
+
+    result[invocation_id.x] = sum;
+}
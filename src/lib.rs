@@ -0,0 +1,11 @@
+pub mod cpu;
+pub mod error;
+pub mod fraction;
+pub mod gpu;
+pub mod lattice;
+pub mod leibniz;
+mod mapped;
+pub mod monte_carlo;
+pub mod polygon;
+mod reduction;
+pub mod version;
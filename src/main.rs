@@ -1,260 +1,1665 @@
-use std::{env::args, mem::size_of};
+use std::{
+    collections::HashSet,
+    env::args,
+    f64::consts::PI,
+    fs::{self, OpenOptions},
+    io::{self, Seek, SeekFrom, Write},
+    panic::{self, AssertUnwindSafe},
+    process,
+    str::FromStr,
+    time::{Duration, Instant},
+};
 
-use wgpu::{
-    include_wgsl, util::DeviceExt, BindGroup, BindGroupLayout, Buffer, BufferDescriptor,
-    BufferUsages, CommandEncoder, ComputePass, Device, InstanceDescriptor, MapMode,
+use picalc::{
+    cpu,
+    error::PiError,
+    fraction::Fraction,
+    gpu::{self, Gpu},
+    lattice::{
+        Jitter, Lattice, Occupancy, Predicate, Readback, Sector, DEFAULT_SIZE, DIVISIONS, MAX_SIZE,
+        WAVE_WIDTH,
+    },
+    leibniz::Leibniz,
+    monte_carlo::{self, MonteCarlo, Points, Sampled},
+    polygon::Polygon,
+    version,
 };
+use wgpu::Features;
 
 fn main() {
     pollster::block_on(run());
 }
 
-struct Square {
-    bind_group: BindGroup,
-    read_buffer: Buffer,
-    storage_buffer: Buffer,
-    _offset_buffer: Buffer,
+enum Format {
+    /// `pi = ...` and whatever else was asked for, for people.
+    Plain,
+    /// Exactly one tab separated line `size numerator denominator pi error`, with no
+    /// header, for logs and `awk`. `size` is the method's measure of work: the lattice's
+    /// size, Monte Carlo's samples, the polygon's sides or the Leibniz series' terms.
+    /// `numerator` and `denominator` are left empty when the estimate isn't an exact
+    /// fraction, and `error` is always the distance from the true value. The columns won't
+    /// change.
+    Compact,
+    /// A JSON object per line: `{"square": [x, y], "weight": w, "count": n}` for each of the
+    /// lattice's squares that went to the GPU, by x and then y once they've all been read
+    /// back, and then one last `{"pi": ..., "error": ...}` with `"fraction": "n/d"` too when
+    /// it's exact. Full squares are counted without the GPU and never show up. Sweeps leave
+    /// out the squares and print a `{"size": n, "pi": ...}` per size, as
+    /// [`Format::NdjsonErrors`] does.
+    Json,
+    /// For sweeps: a JSON object per size, `{"size": n, "pi": ...}` with `"fraction"` when
+    /// it's exact, or `{"size": n, "error": "..."}` with the message when counting that size
+    /// failed. The sweep carries on past failures and exits with 1 if there were any.
+    NdjsonErrors,
+    /// For sweeps in a terminal: the columns of [`Format::Compact`] under a header, padded
+    /// to widths worked out from the largest size before the first one is counted, so the
+    /// rows line up as they come in. A value too wide for its column, as from a lot of
+    /// `--digits`, pushes the rest of its row over rather than being cut.
+    Aligned,
+    /// A line of LaTeX math to paste into a document, `\frac{n}{d} \approx 3.14159\ldots`,
+    /// or just the decimal when the estimate isn't an exact fraction. Sweeps print one per
+    /// size.
+    Latex,
+}
 
-    squares_per: u32,
-    size: u32,
+enum Method {
+    Lattice,
+    MonteCarlo,
+    Polygon,
+    Leibniz,
 }
-struct SquareCommonOptions<'a> {
-    device: &'a Device,
-    squares_per: u32,
-    size: u32,
 
-    storage_buffer_descriptor: &'a BufferDescriptor<'a>,
-    read_buffer_descriptor: &'a BufferDescriptor<'a>,
+struct Args {
+    size: usize,
+    method: Method,
+    /// Radius of the lattice circle, `size - 1` unless given.
+    radius: Option<f32>,
+    /// Set to [`Readback::SingleWorkgroup`] by `--single-workgroup`, which also checks
+    /// it against the normal tiled count.
+    readback: Readback,
+    predicate: Predicate,
+    /// Significant bits to truncate the radius to before counting, which also counts it at
+    /// full precision to compare.
+    radius_bits: Option<u32>,
+    /// Count all four quadrants of the plane by themselves and check they agree.
+    symmetry_check: bool,
+    /// Count the squares this lists at their moved points instead, as read from the file
+    /// given to `--seed-from`.
+    jitter: Option<Jitter>,
+    /// Read the lattice's storage buffers directly instead of through a copy, where the
+    /// adapter allows it.
+    map_storage: bool,
+    /// Copy and map each of the lattice's squares back in this many pieces.
+    read_ranges: u32,
+    /// Only count the lattice points at these angles, scaling the estimate up by the
+    /// sector's share of the quarter.
+    sector: Option<Sector>,
+    /// Print how many counted points fall in each of this many bins by distance from the
+    /// origin, instead of the estimate.
+    distance_histogram: Option<usize>,
+    /// Print how well the lattice's dispatches fit the adapter, instead of the estimate.
+    occupancy: bool,
+    /// Count the lattice leaving out and then taking in the points on the circle, and print
+    /// both estimates instead of the one.
+    boundary_both: bool,
+    /// Estimate from [`Lattice::fast_count`] without touching the GPU.
+    fast: bool,
+    /// Recount on the CPU and check the GPU agrees.
+    verify: bool,
+    /// Print the fraction exactly as counted instead of in lowest terms.
+    no_reduce_fraction: bool,
+    /// Print the estimate as a decimal with this many places.
+    digits: Option<usize>,
+    /// Also print how far off the estimate is: the distance from the true value for the
+    /// lattice, which is deterministic, and the standard error for Monte Carlo.
+    show_error: bool,
+    format: Format,
+    /// Count the lattice at every doubling of `size` up to this.
+    sweep: Option<usize>,
+    /// Where the sweep keeps its finished sizes, so it can carry on after an interruption.
+    resume_sweep: Option<String>,
+    /// Count every whole radius below `size` and print the mean counting error of each
+    /// class of radii modulo this.
+    residue_study: Option<u32>,
+    /// Walk through how the lattice count turned into the estimate before printing it.
+    explain_math: bool,
+    /// Print the versions, adapter and shader hashes behind the results and nothing else.
+    version_info: bool,
+    /// Time the lattice of this size on every adapter instead of estimating anything.
+    bench_adapters: Option<usize>,
+    /// Leave the estimate in this file, laid out as [`write_shared`] describes, instead of
+    /// printing it.
+    shared_file: Option<String>,
+    /// Add the run to the Prometheus metrics in this file, as [`write_metrics`] describes.
+    metrics_file: Option<String>,
+    /// How many more times to look for an adapter when there isn't one yet.
+    adapter_retries: u32,
+    /// Have wgpu trace every call on the device into this directory, which slows the run
+    /// right down. Needs the `trace` feature.
+    trace: Option<String>,
 
-    bind_group_layout: &'a BindGroupLayout,
+    samples: u64,
+    /// Count the points listed in the file given to `--samples-from` instead of drawing
+    /// `samples` of them.
+    sample_points: Option<Points>,
+    seed: u32,
+    sides: u32,
+    terms: u64,
+    /// Add up the polygon's or the Leibniz series' partials with compensated summation.
+    kahan: bool,
+    /// Print `n error` pairs as the estimate converges: Monte Carlo's running total of
+    /// samples, one per batch, the polygon's sides, doubling from a triangle, or the
+    /// Leibniz series' terms, doubling from one.
+    convergence: bool,
 }
-impl Square {
-    fn new(offset: [u32; 2], common_opts: &SquareCommonOptions) -> Self {
-        let _offset_buffer =
-            common_opts
-                .device
-                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                    label: Some("Bottom Right Offset Buffer"),
-                    contents: bytemuck::cast_slice(&offset),
-                    usage: BufferUsages::UNIFORM,
-                });
-        let storage_buffer = common_opts
-            .device
-            .create_buffer(common_opts.storage_buffer_descriptor);
-        let read_buffer = common_opts
-            .device
-            .create_buffer(common_opts.read_buffer_descriptor);
-
-        let bind_group = common_opts
-            .device
-            .create_bind_group(&wgpu::BindGroupDescriptor {
-                label: None,
-                layout: common_opts.bind_group_layout,
-                entries: &[
-                    wgpu::BindGroupEntry {
-                        binding: 0,
-                        resource: storage_buffer.as_entire_binding(),
-                    },
-                    wgpu::BindGroupEntry {
-                        binding: 1,
-                        resource: _offset_buffer.as_entire_binding(),
-                    },
-                ],
-            });
-
-        Self {
-            _offset_buffer,
-            storage_buffer,
-            read_buffer,
-            bind_group,
-            squares_per: common_opts.squares_per,
-            size: common_opts.size,
-        }
-    }
-
-    fn compute<'a>(&'a self, cpass: &mut ComputePass<'a>) {
-        cpass.set_bind_group(1, &self.bind_group, &[]);
-        cpass.dispatch_workgroups(
-            self.size / (self.squares_per * 16),
-            self.size / (self.squares_per * 16),
-            1,
-        );
+
+impl Args {
+    fn parse() -> Self {
+        let mut parsed = Self {
+            size: DEFAULT_SIZE,
+            method: Method::Lattice,
+            radius: None,
+            readback: Readback::Grid,
+            predicate: Predicate::Circle,
+            radius_bits: None,
+            symmetry_check: false,
+            jitter: None,
+            map_storage: false,
+            read_ranges: 1,
+            sector: None,
+            distance_histogram: None,
+            occupancy: false,
+            boundary_both: false,
+            fast: false,
+            verify: false,
+            no_reduce_fraction: false,
+            digits: None,
+            show_error: false,
+            format: Format::Plain,
+            sweep: None,
+            resume_sweep: None,
+            residue_study: None,
+            explain_math: false,
+            version_info: false,
+            bench_adapters: None,
+            shared_file: None,
+            metrics_file: None,
+            adapter_retries: gpu::DEFAULT_RETRIES,
+            trace: None,
+            samples: 1 << 24,
+            sample_points: None,
+            seed: 0,
+            sides: 1 << 16,
+            terms: 1 << 24,
+            kahan: false,
+            convergence: false,
+        };
+
+        let mut args = args().skip(1);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--help" | "-h" => help(),
+                "--method" => {
+                    parsed.method = match value::<String>(&arg, args.next()).as_str() {
+                        "lattice" => Method::Lattice,
+                        "monte-carlo" => Method::MonteCarlo,
+                        "polygon" => Method::Polygon,
+                        "leibniz" => Method::Leibniz,
+                        method => usage_error(&format!("unknown method '{}'", method)),
+                    }
+                }
+                "--radius" => parsed.radius = Some(value(&arg, args.next())),
+                "--predicate" => parsed.predicate = value(&arg, args.next()),
+                "--curve" => {
+                    parsed.predicate = match value::<String>(&arg, args.next()).as_str() {
+                        "quarter-circle" => Predicate::Circle,
+                        "sqrt" => Predicate::Sqrt,
+                        _ => usage_error("--curve must be sqrt or quarter-circle"),
+                    }
+                }
+                "--radius-bits" => {
+                    parsed.radius_bits = match value(&arg, args.next()) {
+                        bits @ 1..=24 => Some(bits),
+                        _ => usage_error("--radius-bits must be from 1 to 24"),
+                    }
+                }
+                "--map-storage" => parsed.map_storage = true,
+                "--read-ranges" => parsed.read_ranges = value(&arg, args.next()),
+                "--symmetry-check" => parsed.symmetry_check = true,
+                "--seed-from" => parsed.jitter = Some(jitter(&value::<String>(&arg, args.next()))),
+                "--sector" => parsed.sector = Some(value(&arg, args.next())),
+                "--readback" => parsed.readback = value(&arg, args.next()),
+                "--distance-histogram" => {
+                    parsed.distance_histogram = Some(value(&arg, args.next()))
+                }
+                "--single-workgroup" => parsed.readback = Readback::SingleWorkgroup,
+                "--occupancy" => parsed.occupancy = true,
+                "--boundary-both" => parsed.boundary_both = true,
+                "--fast" => parsed.fast = true,
+                "--verify" => parsed.verify = true,
+                "--no-reduce-fraction" => parsed.no_reduce_fraction = true,
+                "--digits" => parsed.digits = Some(value(&arg, args.next())),
+                "--show-error" => parsed.show_error = true,
+                "--format" => {
+                    parsed.format = match value::<String>(&arg, args.next()).as_str() {
+                        "plain" => Format::Plain,
+                        "compact" => Format::Compact,
+                        "json" => Format::Json,
+                        "ndjson-errors" => Format::NdjsonErrors,
+                        "aligned" => Format::Aligned,
+                        "latex" => Format::Latex,
+                        format => usage_error(&format!("unknown format '{}'", format)),
+                    }
+                }
+                "--compact" => parsed.format = Format::Compact,
+                "--samples" => parsed.samples = value(&arg, args.next()),
+                "--samples-from" => {
+                    parsed.sample_points = Some(points(&value::<String>(&arg, args.next())))
+                }
+                "--seed" => parsed.seed = value(&arg, args.next()),
+                "--sides" => parsed.sides = value(&arg, args.next()),
+                "--terms" => parsed.terms = value(&arg, args.next()),
+                "--kahan" => parsed.kahan = true,
+                "--convergence" => parsed.convergence = true,
+                "--sweep" => parsed.sweep = Some(size(&value::<String>(&arg, args.next()))),
+                "--resume-sweep" => parsed.resume_sweep = Some(value(&arg, args.next())),
+                "--residue-study" => parsed.residue_study = Some(value(&arg, args.next())),
+                "--explain-math" => parsed.explain_math = true,
+                "--version-info" => parsed.version_info = true,
+                "--bench-adapters" => {
+                    parsed.bench_adapters = Some(size(&value::<String>(&arg, args.next())))
+                }
+                "--shared-file" => parsed.shared_file = Some(value(&arg, args.next())),
+                "--metrics-file" => parsed.metrics_file = Some(value(&arg, args.next())),
+                "--adapter-retries" => parsed.adapter_retries = value(&arg, args.next()),
+                "--trace" => parsed.trace = Some(value(&arg, args.next())),
+                _ if arg.starts_with("--") => usage_error(&format!("unknown option '{}'", arg)),
+                _ => parsed.size = size(&arg),
+            }
+        }
+
+        if parsed.convergence && matches!(parsed.method, Method::Lattice) {
+            usage_error("--convergence needs --method monte-carlo, polygon or leibniz");
+        }
+        if parsed.resume_sweep.is_some() && parsed.sweep.is_none() {
+            usage_error("--resume-sweep needs --sweep");
+        }
+        if parsed.sweep.is_some() && !matches!(parsed.method, Method::Lattice) {
+            usage_error("--sweep only works with the lattice");
+        }
+        if matches!(parsed.format, Format::NdjsonErrors) && parsed.sweep.is_none() {
+            usage_error("--format ndjson-errors needs --sweep");
+        }
+        if matches!(parsed.format, Format::Aligned) && parsed.sweep.is_none() {
+            usage_error("--format aligned needs --sweep");
+        }
+        if parsed.sweep.is_some() && parsed.radius.is_some() {
+            usage_error("--sweep always uses the default radius of each size");
+        }
+        if parsed.sector.is_some() && parsed.predicate != Predicate::Circle {
+            usage_error("--sector only works with the circle, whose area is even in angle");
+        }
+        if parsed.explain_math
+            && !(matches!(parsed.method, Method::Lattice)
+                && matches!(parsed.format, Format::Plain)
+                && parsed.sweep.is_none())
+        {
+            usage_error("--explain-math only explains a single lattice count in the plain format");
+        }
+        // everything else prints a table or a series and leaves no Report behind
+        let single = parsed.sweep.is_none()
+            && !parsed.convergence
+            && parsed.distance_histogram.is_none()
+            && parsed.residue_study.is_none();
+        if parsed.shared_file.is_some() && !single {
+            usage_error("--shared-file only holds a single estimate");
+        }
+        if parsed.metrics_file.is_some() && !single {
+            usage_error("--metrics-file only records single estimates");
+        }
+        if parsed.occupancy && !(matches!(parsed.method, Method::Lattice) && parsed.sweep.is_none())
+        {
+            usage_error("--occupancy only looks at a single lattice count");
+        }
+        if parsed.boundary_both {
+            if !(matches!(parsed.method, Method::Lattice) && single) || parsed.occupancy {
+                usage_error("--boundary-both only compares a single lattice count");
+            }
+            if parsed.predicate != Predicate::Circle || parsed.sector.is_some() {
+                usage_error("--boundary-both only works with the whole circle");
+            }
+            if parsed.jitter.is_some() || parsed.fast {
+                usage_error("--boundary-both needs the points on the lattice, counted exactly");
+            }
+            if parsed
+                .radius
+                .is_some_and(|radius| radius >= parsed.size as f32)
+            {
+                usage_error("--boundary-both needs a radius inside the grid, below size");
+            }
+        }
+        if parsed.fast {
+            if !(matches!(parsed.method, Method::Lattice) && single) || parsed.occupancy {
+                usage_error("--fast only makes a single lattice estimate");
+            }
+            if parsed.verify || parsed.symmetry_check || parsed.jitter.is_some() {
+                usage_error("--fast counts nothing on the GPU to check");
+            }
+            if parsed.sector.is_some() || parsed.explain_math {
+                usage_error("--fast doesn't count the points a sector or --explain-math needs");
+            }
+        }
+        if parsed.jitter.is_some() && !(matches!(parsed.method, Method::Lattice) && single) {
+            usage_error("--seed-from only moves the points of a single lattice count");
+        }
+        if let Some(modulus) = parsed.residue_study {
+            if !matches!(parsed.method, Method::Lattice) {
+                usage_error("--residue-study only works with the lattice");
+            }
+            if parsed.predicate != Predicate::Circle || parsed.sector.is_some() {
+                usage_error("--residue-study only works with the whole circle");
+            }
+            if parsed.radius.is_some() || parsed.sweep.is_some() {
+                usage_error("--residue-study picks its own radii");
+            }
+            if modulus == 0 {
+                usage_error("--residue-study needs a modulus of at least 1");
+            }
+        }
+        if cfg!(not(feature = "trace")) && parsed.trace.is_some() {
+            usage_error("--trace needs picalc built with --features trace");
+        }
+        if parsed.kahan && !matches!(parsed.method, Method::Polygon | Method::Leibniz) {
+            usage_error("--kahan only adds up the polygon's and the Leibniz series' floats");
+        }
+        if parsed.read_ranges == 0 {
+            usage_error("--read-ranges must be at least 1");
+        }
+        if parsed.read_ranges > 1 && !matches!(parsed.method, Method::Lattice) {
+            usage_error("--read-ranges only reads back the lattice");
+        }
+        if parsed.read_ranges > 1 && parsed.map_storage {
+            usage_error("--read-ranges copies the storage buffers that --map-storage maps");
+        }
+        if parsed.radius.is_some_and(|radius| {
+            !(radius > 0.0 && radius <= parsed.size as f32 && radius < MAX_SIZE as f32)
+        }) {
+            usage_error(&format!(
+                "--radius must be above 0, at most size and below {}",
+                MAX_SIZE
+            ));
+        }
+        if parsed.distance_histogram == Some(0) {
+            usage_error("--distance-histogram needs at least 1 bin");
+        }
+        if parsed.sample_points.is_some()
+            && (parsed.convergence || !matches!(parsed.method, Method::MonteCarlo))
+        {
+            usage_error("--samples-from only counts a single Monte Carlo estimate");
+        }
+        if parsed.samples == 0 {
+            usage_error("--samples must be at least 1");
+        }
+        if parsed.sides < 3 {
+            usage_error("--sides must be at least 3");
+        }
+        if parsed.terms == 0 {
+            usage_error("--terms must be at least 1");
+        }
+
+        parsed
     }
 
-    fn copy(&self, encoder: &mut CommandEncoder) {
-        encoder.copy_buffer_to_buffer(
-            &self.storage_buffer,
-            0,
-            &self.read_buffer,
-            0,
-            (self.size / self.squares_per * self.size / self.squares_per
-                * (std::mem::size_of::<u32>() as u32))
-                .try_into()
-                .unwrap(),
-        );
+    /// The lattice of `size` with everything the options ask for.
+    fn lattice(&self, size: usize) -> Lattice {
+        let mut lattice = Lattice::new(size);
+        lattice.radius = self.radius.unwrap_or(lattice.radius);
+        lattice.readback = self.readback;
+        lattice.predicate = self.predicate;
+        lattice.sector = self.sector;
+        lattice.map_storage = self.map_storage;
+        lattice.ranges = self.read_ranges;
+        lattice.radius_bits = self.radius_bits.unwrap_or(lattice.radius_bits);
+        lattice
+    }
+}
+
+/// Parses the value given for `flag`, bailing out with a usage error when it's missing or
+/// malformed.
+fn value<T: FromStr>(flag: &str, arg: Option<String>) -> T {
+    let Some(arg) = arg else {
+        usage_error(&format!("{} needs a value", flag));
+    };
+
+    arg.parse()
+        .unwrap_or_else(|_| usage_error(&format!("bad value '{}' for {}", arg, flag)))
+}
+
+fn size(arg: &str) -> usize {
+    match arg.parse::<usize>() {
+        Ok(0) | Err(_) => usage_error(&format!("size must be a positive integer, got '{}'", arg)),
+        Ok(1) => usage_error("size must be at least 2 to leave a nonzero radius"),
+        Ok(size) if size > MAX_SIZE => usage_error(&format!(
+            "size must be at most {}, past which the GPU's squared distances overflow",
+            MAX_SIZE
+        )),
+        Ok(size) => size,
     }
+}
+
+const USAGE: &str = "usage: picalc [size] [--method lattice|monte-carlo|polygon|leibniz] [options]";
+
+/// Reads the jitter file given to `--seed-from`, laid out as [`Jitter`] describes.
+fn jitter(path: &str) -> Jitter {
+    let text = fs::read_to_string(path)
+        .unwrap_or_else(|e| usage_error(&format!("can't read '{}': {}", path, e)));
+    text.parse().unwrap_or_else(|()| {
+        usage_error(&format!(
+            "bad jitter file '{}', want lines of `x y dx dy` with x and y below {} and dx and dy in 0..1",
+            path, DIVISIONS
+        ))
+    })
+}
+
+/// Reads the sample points given to `--samples-from`, laid out as [`Points`] describes.
+fn points(path: &str) -> Points {
+    let text = fs::read_to_string(path)
+        .unwrap_or_else(|e| usage_error(&format!("can't read '{}': {}", path, e)));
+    text.parse().unwrap_or_else(|()| {
+        usage_error(&format!(
+            "bad samples file '{}', want at least one line of `x y`",
+            path
+        ))
+    })
+}
+
+fn usage_error(message: &str) -> ! {
+    eprintln!("error: {}", message);
+    eprintln!("{}", USAGE);
+    process::exit(2);
+}
 
-    fn map(&self) {
-        self.read_buffer
-            .slice(..)
-            .map_async(MapMode::Read, move |e| {
-                e.unwrap();
-            });
+fn help() -> ! {
+    println!("{}", USAGE);
+    println!();
+    println!(
+        "  size                      lattice size up to {}, {} unless given",
+        MAX_SIZE, DEFAULT_SIZE
+    );
+    println!("  --method <method>         lattice, monte-carlo, polygon or leibniz");
+    println!("  --radius <r>              lattice radius, size - 1 unless given");
+    println!("  --radius-bits <n>         truncate the radius to n bits and compare");
+    println!("  --predicate <region>      circle, superellipse:<p>, ellipse:<ratio> or sqrt");
+    println!("  --curve <curve>           count under sqrt or quarter-circle, like --predicate");
+    println!("  --sector <start>:<end>    only count the points at these angles in degrees");
+    println!("  --readback <readback>     grid, rows, reduce or bitpacked");
+    println!("  --symmetry-check          count all four quadrants and check they agree");
+    println!("  --seed-from <file>        move squares' points by the `x y dx dy` lines in file");
+    println!("  --map-storage             read storage buffers without copying, for debugging");
+    println!("  --read-ranges <n>         read each square back in n pieces as they're copied");
+    println!("  --single-workgroup        count each square in one workgroup, for debugging");
+    println!("  --distance-histogram <n>  bin the counted points by distance instead");
+    println!("  --occupancy               print how well the dispatches fit the adapter instead");
+    println!("  --boundary-both           compare counts without and with the circle's points");
+    println!("  --fast                    rough lattice estimate without the GPU, to ~1e-3");
+    println!("  --verify                  check the GPU against the CPU");
+    println!("  --no-reduce-fraction      print the fraction as counted");
+    println!("  --digits <n>              print the estimate to n decimal places");
+    println!("  --show-error              also print how far off the estimate is");
+    println!("  --format <format>         plain, compact, json, ndjson-errors, aligned or latex");
+    println!("  --compact                 same as --format compact");
+    println!("  --samples <n>             Monte Carlo samples");
+    println!("  --samples-from <file>     count the `x y` points in file instead of drawing");
+    println!("  --seed <n>                Monte Carlo seed");
+    println!("  --sides <n>               polygon sides");
+    println!("  --terms <n>               Leibniz series terms");
+    println!("  --kahan                   Kahan-sum the polygon or Leibniz partials");
+    println!("  --convergence             print the error as Monte Carlo or the polygon goes");
+    println!("  --sweep <max>             count the lattice at every doubling of size up to max");
+    println!("  --resume-sweep <file>     keep the sweep in file and carry on from it");
+    println!("  --residue-study <m>       mean counting error of radii below size by r mod m");
+    println!("  --explain-math            show how the lattice count becomes the estimate");
+    println!("  --version-info            print versions, the adapter and shader hashes");
+    println!("  --bench-adapters <size>   time the lattice of size on every adapter");
+    println!("  --shared-file <file>      leave the estimate in file for another process to map");
+    println!("  --metrics-file <file>     add the run to Prometheus metrics kept in file");
+    println!("  --adapter-retries <n>     look for an adapter n more times before giving up");
+    println!("  --trace <dir>             trace wgpu into dir for bug reports, very slowly");
+    process::exit(0);
+}
+
+/// Something the GPU got wrong, which there's no carrying on from.
+fn fail<T>(error: PiError) -> T {
+    eprintln!("error: {}", error);
+    process::exit(1);
+}
+
+/// Progress and check results, kept off stdout unless it's for people anyway.
+fn status(args: &Args, message: &str) {
+    match args.format {
+        Format::Plain if args.sweep.is_none() && args.shared_file.is_none() => {
+            println!("{}", message)
+        }
+        _ => eprintln!("{}", message),
     }
+}
+
+/// Checks a GPU lattice count against the CPU's. The CPU only counts the whole quarter,
+/// so a sector's count gets the rest of the quarter added from the GPU first, which also
+/// checks the sectors split the points between them exactly.
+fn verify(args: &Args, gpu: &Gpu, lattice: &Lattice, total: u64) -> Result<(), PiError> {
+    let radius = lattice.counted_radius();
+    let total = total
+        + lattice
+            .sector
+            .map_or(Vec::new(), Sector::complement)
+            .into_iter()
+            .map(|sector| {
+                Lattice {
+                    sector: Some(sector),
+                    ..*lattice
+                }
+                .count(gpu)
+            })
+            .sum::<Result<u64, _>>()?;
+
+    let expected = cpu::lattice_count(args.predicate, radius);
+    let ambiguous = cpu::ambiguous_count(args.predicate, radius);
+    assert!(
+        total.abs_diff(expected) <= ambiguous,
+        "GPU and CPU Counts Differ: {} != {}",
+        total,
+        expected
+    );
+    status(args, "CPU Agrees!");
+
+    lattice.check_offsets(gpu)?;
+    status(args, "Offsets Agree!");
+    Ok(())
+}
 
-    fn get_total(&self) -> u32 {
-        let data = self.read_buffer.slice(..).get_mapped_range();
+/// What a run came up with, ready to print in any format.
+struct Report {
+    /// How much work went in: the lattice's size, Monte Carlo's samples, the polygon's
+    /// sides or the Leibniz series' terms.
+    n: u64,
+    /// The exact count based estimate, where there is one.
+    fraction: Option<Fraction>,
+    estimate: f64,
+    /// The value the estimate converges to, pi for everything but the superellipse and the
+    /// square root.
+    constant: f64,
+    /// Monte Carlo's statistical uncertainty, which `--show-error` prints instead of the
+    /// distance from `constant`.
+    standard_error: Option<f64>,
+}
 
-        let data_u32: &[u32] = bytemuck::cast_slice(&data);
+impl Report {
+    fn error(&self) -> f64 {
+        (self.estimate - self.constant).abs()
+    }
 
-        data_u32.iter().sum()
+    fn fraction(&self, args: &Args) -> Option<Fraction> {
+        match self.fraction {
+            Some(fraction) if args.no_reduce_fraction => Some(fraction),
+            fraction => fraction.map(Fraction::reduced),
+        }
     }
+}
 
-    #[allow(dead_code)]
-    fn print(&self) {
-        let data = self.read_buffer.slice(..).get_mapped_range();
+/// Formats an estimate exactly as its fraction when there is one, otherwise as a float,
+/// or as a decimal to `--digits` places when asked.
+fn format_estimate(args: &Args, report: &Report) -> String {
+    match (args.digits, report.fraction(args)) {
+        (None, Some(fraction)) => fraction.to_string(),
+        _ => format_decimal(args, report),
+    }
+}
+
+/// Formats an estimate as a decimal, to `--digits` places if given, warning when those
+/// are more than the estimate or an f64 can back up.
+fn format_decimal(args: &Args, report: &Report) -> String {
+    let Some(digits) = args.digits else {
+        return report.estimate.to_string();
+    };
 
-        let data_u32: &[u32] = bytemuck::cast_slice(&data);
+    let error = report.error();
+    let meaningful = (-error.log10()).floor().max(0.0) as usize;
+    if error > 0.0 && digits > meaningful {
+        eprintln!(
+            "warning: the estimate is only within {:.1e} of the true value, so just the first {} of {} decimal places mean anything",
+            error, meaningful, digits
+        );
+    }
 
-        for y in 0..self.size / self.squares_per {
-            for x in 0..self.size / self.squares_per {
-                print!(
-                    "{}",
-                    data_u32[((y * self.size) / self.squares_per + x) as usize]
+    match report.fraction {
+        Some(fraction) => fraction.decimal(digits),
+        None => {
+            // f64 carries 15 significant digits, some of which go before the point
+            let faithful = (f64::DIGITS as i32 - report.estimate.abs().log10().floor() as i32 - 1)
+                .max(0) as usize;
+            if digits > faithful {
+                eprintln!(
+                    "warning: an f64 only carries {} decimal places of this estimate, printing those instead of {}",
+                    faithful, digits
                 );
             }
-            println!();
+            format!("{:.*}", digits.min(faithful), report.estimate)
         }
     }
 }
 
-async fn run() {
-    let size = args()
-        .nth(1)
-        .map(|x| x.parse::<usize>().expect("Bad Arg Format"))
-        .unwrap_or(1024);
+fn compact_line(args: &Args, report: &Report) -> String {
+    let (numerator, denominator) = report
+        .fraction(args)
+        .map(|x| (x.numerator.to_string(), x.denominator.to_string()))
+        .unwrap_or_default();
 
-    env_logger::init();
+    format!(
+        "{}\t{}\t{}\t{}\t{:e}",
+        report.n,
+        numerator,
+        denominator,
+        format_decimal(args, report),
+        report.error()
+    )
+}
 
-    let instance = wgpu::Instance::new(InstanceDescriptor::default());
+/// An estimate as `--format latex` prints it.
+fn latex_line(args: &Args, report: &Report) -> String {
+    let decimal = format!("{}\\ldots", format_decimal(args, report));
+    match report.fraction(args) {
+        Some(x) => format!(
+            "\\frac{{{}}}{{{}}} \\approx {}",
+            x.numerator, x.denominator, decimal
+        ),
+        None => decimal,
+    }
+}
 
-    let adapter = instance
-        .request_adapter(&wgpu::RequestAdapterOptions::default())
-        .await
-        .unwrap();
-
-
-    let (device, queue) = adapter
-        .request_device(
-            &wgpu::DeviceDescriptor {
-                label: None,
-                features: wgpu::Features::empty(),
-                limits: adapter.limits(),
-            },
-            None,
-        )
-        .await
-        .unwrap();
+/// How wide [`aligned_row`] pads each column but the last for a sweep up to `max`, wide
+/// enough for the header and for the largest value each column can hold: the lattice's
+/// numerator is at most `4 max^2` and its denominator `max^2`.
+fn aligned_widths(args: &Args, max: usize) -> [usize; 4] {
+    let digits = |x: u128| x.to_string().len();
+    let max = max as u128;
+    // an f64 prints at most 17 significant digits
+    let pi = args.digits.map_or(18, |digits| digits + 2);
+
+    [
+        digits(max).max("size".len()),
+        digits(4 * max * max).max("numerator".len()),
+        digits(max * max).max("denominator".len()),
+        pi,
+    ]
+}
+
+/// The cells of a `--format aligned` row, the numbers right aligned and the rest left.
+fn aligned_row(cells: [&str; 5], widths: [usize; 4]) -> String {
+    let [size, numerator, denominator, pi, error] = cells;
+    format!(
+        "{:>w0$}  {:>w1$}  {:>w2$}  {:<w3$}  {}",
+        size,
+        numerator,
+        denominator,
+        pi,
+        error,
+        w0 = widths[0],
+        w1 = widths[1],
+        w2 = widths[2],
+        w3 = widths[3],
+    )
+}
+
+/// A finished size of a `--format ndjson-errors` sweep.
+fn ndjson_line(args: &Args, report: &Report) -> String {
+    let fraction = report
+        .fraction(args)
+        .map(|x| format!(", \"fraction\": \"{}\"", x))
+        .unwrap_or_default();
+
+    format!(
+        "{{\"size\": {}, \"pi\": {}{}}}",
+        report.n,
+        format_decimal(args, report),
+        fraction
+    )
+}
+
+/// Quotes `s` as a JSON string.
+fn json_string(s: &str) -> String {
+    let mut quoted = String::from('"');
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Runs `f`, turning a panic into its message. The panic isn't printed as well, since the
+/// message is going to end up in the output.
+fn catch_panic<T>(f: impl FnOnce() -> T) -> Result<T, String> {
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| {}));
+    let result = panic::catch_unwind(AssertUnwindSafe(f));
+    panic::set_hook(hook);
+
+    result.map_err(|payload| {
+        payload
+            .downcast_ref::<&str>()
+            .map(|x| x.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "unknown panic".to_string())
+    })
+}
+
+fn print_report(args: &Args, report: &Report) {
+    match args.format {
+        Format::Plain => {
+            let formatted = format_estimate(args, report);
+            if report.constant == PI {
+                println!("pi = {}", formatted);
+            } else {
+                println!("constant = {} (expected {})", formatted, report.constant);
+            }
+
+            if args.show_error {
+                match report.standard_error {
+                    Some(standard_error) => println!("standard error = {:e}", standard_error),
+                    None => println!("error = {:e}", report.error()),
+                }
+            }
+        }
+        Format::Compact | Format::Aligned => println!("{}", compact_line(args, report)),
+        Format::Latex => println!("{}", latex_line(args, report)),
+        Format::Json | Format::NdjsonErrors => {
+            let fraction = report
+                .fraction(args)
+                .map(|x| format!(", \"fraction\": \"{}\"", x))
+                .unwrap_or_default();
+            println!(
+                "{{\"pi\": {}, \"error\": {:e}{}}}",
+                format_decimal(args, report),
+                report.error(),
+                fraction
+            );
+        }
+    }
+}
+
+/// Bytes [`write_shared`] writes.
+const SHARED_SIZE: usize = 56;
+
+/// Leaves the estimate in `path` for a process that maps the file, as seven little endian
+/// 8 byte fields:
+///
+/// | offset | field                                                   |
+/// |--------|---------------------------------------------------------|
+/// | 0      | `u64` ready flag, 1 once everything after it is written |
+/// | 8      | `u64` n, the size, samples, sides or terms              |
+/// | 16     | `u64` numerator of the exact fraction, 0 without one    |
+/// | 24     | `u64` denominator of the exact fraction, 0 without one  |
+/// | 32     | `f64` estimate                                          |
+/// | 40     | `f64` the constant it converges to                      |
+/// | 48     | `f64` standard error, NaN where there isn't one         |
+///
+/// The file is never truncated, so a reader that already mapped it doesn't fault on it
+/// shrinking. The flag is cleared and synced, then the rest written and synced before the
+/// flag is set, so a reader that sees 1 at offset 0 can trust the rest.
+fn write_shared(args: &Args, path: &str, report: &Report) -> io::Result<()> {
+    let fraction = report.fraction(args).unwrap_or(Fraction::new(0, 0));
+
+    let mut bytes = Vec::with_capacity(SHARED_SIZE - 8);
+    for field in [
+        report.n,
+        fraction.numerator,
+        fraction.denominator,
+        report.estimate.to_bits(),
+        report.constant.to_bits(),
+        report.standard_error.unwrap_or(f64::NAN).to_bits(),
+    ] {
+        bytes.extend_from_slice(&field.to_le_bytes());
+    }
 
-    let shader = device.create_shader_module(include_wgsl!("compute.wgsl"));
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)?;
+    file.set_len(SHARED_SIZE as u64)?;
+    file.write_all(&0u64.to_le_bytes())?;
+    file.sync_data()?;
 
-    let storage_buffer_descriptor = BufferDescriptor {
-        label: Some("Storage Buffer"),
-        size: (size_of::<u32>() * size / 8 * size / 8) as u64,
-        usage: BufferUsages::STORAGE | BufferUsages::COPY_SRC,
-        mapped_at_creation: false,
+    file.write_all(&bytes)?;
+    file.sync_data()?;
+
+    file.seek(SeekFrom::Start(0))?;
+    file.write_all(&1u64.to_le_bytes())?;
+    file.sync_data()
+}
+
+/// Adds a run that took `elapsed` to the metrics in `path`, in Prometheus' text format for
+/// node exporter's textfile collector to pick up. The counters carry on from the ones
+/// already in the file, and the gauges are this run's:
+///
+/// - `picalc_computations_total`: estimates computed.
+/// - `picalc_compute_seconds_total`: time spent computing them, checks included.
+/// - `picalc_last_estimate`: the latest estimate.
+/// - `picalc_last_error`: how far the latest estimate was from its constant.
+///
+/// The names are kept as they are so dashboards don't break. The file is replaced in one
+/// rename, so the collector never reads half of it.
+fn write_metrics(path: &str, elapsed: Duration, report: &Report) -> io::Result<()> {
+    let previous = fs::read_to_string(path).unwrap_or_default();
+    let counter = |name: &str| {
+        previous
+            .lines()
+            .filter_map(|line| line.split_once(' '))
+            .find(|&(metric, _)| metric == name)
+            .and_then(|(_, value)| value.trim().parse::<f64>().ok())
+            .unwrap_or(0.0)
     };
 
-    let read_buffer_descriptor = BufferDescriptor {
-        label: Some("Read Buffer"),
-        size: (size_of::<u32>() * size / 8 * size / 8) as u64,
-        usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
-        mapped_at_creation: false,
+    let metrics = [
+        (
+            "picalc_computations_total",
+            "counter",
+            "Estimates computed.",
+            counter("picalc_computations_total") + 1.0,
+        ),
+        (
+            "picalc_compute_seconds_total",
+            "counter",
+            "Time spent computing estimates, checks included.",
+            counter("picalc_compute_seconds_total") + elapsed.as_secs_f64(),
+        ),
+        (
+            "picalc_last_estimate",
+            "gauge",
+            "The latest estimate.",
+            report.estimate,
+        ),
+        (
+            "picalc_last_error",
+            "gauge",
+            "How far the latest estimate was from its constant.",
+            report.error(),
+        ),
+    ];
+
+    let mut text = String::new();
+    for (name, kind, help, value) in metrics {
+        text += &format!(
+            "# HELP {} {}\n# TYPE {} {}\n{} {}\n",
+            name, help, name, kind, name, value
+        );
+    }
+
+    let partial = format!("{}.tmp", path);
+    fs::write(&partial, text)?;
+    fs::rename(partial, path)
+}
+
+fn lattice_report(args: &Args, gpu: &Gpu, lattice: &Lattice) -> Result<Report, PiError> {
+    let radius = lattice.radius;
+
+    let total = lattice.count_with(gpu, |[x, y], weight, count| {
+        if let (Format::Json, None) = (&args.format, args.sweep) {
+            println!(
+                "{{\"square\": [{}, {}], \"weight\": {}, \"count\": {}}}",
+                x, y, weight, count
+            );
+        }
+    })?;
+    status(args, "GPU Done!");
+
+    if args.symmetry_check {
+        let counts = lattice.quadrant_counts(gpu)?;
+        for (quadrant, count) in counts.iter().enumerate() {
+            status(args, &format!("quadrant {} = {}", quadrant + 1, count));
+        }
+        assert!(
+            counts.iter().all(|&x| x == counts[0]),
+            "Quadrant Counts Differ: {:?}",
+            counts
+        );
+        status(args, "Quadrants Agree!");
+    }
+
+    if lattice.readback == Readback::SingleWorkgroup {
+        let tiled = Lattice {
+            readback: Readback::Grid,
+            ..*lattice
+        }
+        .count(gpu)?;
+        assert!(
+            total == tiled,
+            "Single Workgroup and Tiled Counts Differ: {} != {}",
+            total,
+            tiled
+        );
+        status(args, "Tiling Agrees!");
+    }
+
+    if args.verify {
+        verify(args, gpu, lattice, total)?;
+    }
+
+    // everything above checks the lattice itself, and the jitter only changes what's reported
+    let total = match &args.jitter {
+        Some(jitter) => jittered_count(args, gpu, lattice, jitter, total)?,
+        None => total,
     };
 
-    let options_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-        label: Some("Size Buffer"),
-        contents: bytemuck::cast_slice(&[size as u32, (size / 8) as u32]),
-        usage: BufferUsages::UNIFORM,
-    });
+    let report = Report {
+        n: lattice.size as u64,
+        fraction: lattice.fraction(total),
+        estimate: lattice.estimate(total),
+        constant: args.predicate.constant(),
+        standard_error: None,
+    };
 
-    let compute_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
-        label: None,
-        layout: None,
-        module: &shader,
-        entry_point: "main",
-    });
+    if lattice.radius_bits < f32::MANTISSA_DIGITS {
+        let full = Lattice {
+            radius_bits: f32::MANTISSA_DIGITS,
+            ..*lattice
+        }
+        .count(gpu)?;
+        let full_estimate = lattice.estimate(full);
 
-    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-        label: None,
-        layout: &compute_pipeline.get_bind_group_layout(0),
-        entries: &[wgpu::BindGroupEntry {
-            binding: 0,
-            resource: options_buffer.as_entire_binding(),
-        }],
-    });
+        status(
+            args,
+            &format!(
+                "radius {} counted as {} at {} bits",
+                radius,
+                lattice.counted_radius(),
+                lattice.radius_bits
+            ),
+        );
+        status(
+            args,
+            &format!(
+                "full precision estimate = {} ({} points and {:e} away)",
+                full_estimate,
+                full.abs_diff(total),
+                (full_estimate - report.estimate).abs()
+            ),
+        );
+    }
+
+    if args.explain_math {
+        explain_math(args, lattice, total, &report);
+    }
+
+    Ok(report)
+}
+
+/// Counts the lattice with `jitter` moving its points, given the count without. With
+/// `--verify` the same squares are counted again moved by nothing first, which must match
+/// the lattice exactly.
+fn jittered_count(
+    args: &Args,
+    gpu: &Gpu,
+    lattice: &Lattice,
+    jitter: &Jitter,
+    total: u64,
+) -> Result<u64, PiError> {
+    if args.verify {
+        let zeroed = lattice.count_jittered(gpu, &jitter.zeroed())?;
+        assert!(
+            zeroed == total,
+            "Zero Jitter and Lattice Counts Differ: {} != {}",
+            zeroed,
+            total
+        );
+        status(args, "Zero Jitter Agrees!");
+    }
+
+    let jittered = lattice.count_jittered(gpu, jitter)?;
+    status(
+        args,
+        &format!(
+            "jittered count = {} ({} points from the lattice's {})",
+            jittered,
+            jittered.abs_diff(total),
+            total
+        ),
+    );
+    Ok(jittered)
+}
 
-    let offset_bind_group_layout = compute_pipeline.get_bind_group_layout(1);
+/// Walks through how the lattice's count turns into the estimate, with this run's numbers.
+fn explain_math(args: &Args, lattice: &Lattice, total: u64, report: &Report) {
+    let r = lattice.radius;
 
-    let common_opts = SquareCommonOptions {
-        device: &device,
-        squares_per: 8,
-        size: size as u32,
-        storage_buffer_descriptor: &storage_buffer_descriptor,
-        read_buffer_descriptor: &read_buffer_descriptor,
-        bind_group_layout: &offset_bind_group_layout,
+    let (region, shape, area, constant, divisor) = match args.predicate {
+        Predicate::Circle => (
+            "x^2 + y^2 < r^2".to_string(),
+            "quarter circle",
+            "pi r^2 / 4",
+            "pi",
+            "r^2",
+        ),
+        Predicate::Superellipse(p) => (
+            format!("|x/r|^{0} + |y/r|^{0} < 1", p),
+            "quarter superellipse",
+            "C r^2 / 4",
+            "C",
+            "r^2",
+        ),
+        Predicate::Ellipse(b) => (
+            format!("x^2 + (y/b)^2 < r^2 for b = {}", b),
+            "quarter ellipse",
+            "pi r^2 b / 4",
+            "pi",
+            "r^2 b",
+        ),
+        Predicate::Sqrt => (
+            "y < sqrt(r (r - x))".to_string(),
+            "mirrored square root",
+            "2 r^2 / 3 = C r^2 / 4",
+            "C",
+            "r^2",
+        ),
     };
 
-    let s = (size / 8) as u32;
-    let squares = [
-        [7, 0],
-        [7, 1],
-        [7, 2],
-        [7, 3],
-        [6, 3],
-        [6, 4],
-        [6, 5],
-        [5, 5],
-    ]
-    .map(|x| Square::new([x[0] * s, x[1] * s], &common_opts));
+    println!("How the count becomes the estimate:");
+    println!("  1. Every lattice point (x, y) is the corner of one unit square, so the number of");
+    println!("     points inside a region is close to its area.");
+    println!(
+        "  2. With radius r = {}, N = {} points have {}.",
+        r, total, region
+    );
+    println!(
+        "  3. They fill the {} of area {}, so N ~ {}.",
+        shape, area, area
+    );
 
-    let mut encoder =
-        device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+    let divisor = match lattice.sector {
+        Some(sector) => {
+            let share = sector.share();
+            println!(
+                "     Only those from {} to {} degrees were counted, s = {} of the quarter,",
+                sector.start, sector.end, share
+            );
+            println!("     so N ~ s {}.", area);
+            format!("({} s)", divisor)
+        }
+        None => divisor.to_string(),
+    };
+
+    let divided = lattice.normalizer();
+    println!(
+        "  4. Solving for {0} gives {0} ~ 4 N / {1}",
+        constant, divisor
+    );
+    println!("       = 4 x {} / {}", total, divided);
+    println!("       = {} / {}", total * 4, divided);
+    if let Some(fraction) = report
+        .fraction(args)
+        .filter(|&x| x != Fraction::new(total * 4, divided as u64))
     {
-        let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor { label: None });
-        cpass.set_pipeline(&compute_pipeline);
-        cpass.set_bind_group(0, &bind_group, &[]);
+        println!("       = {}", fraction);
+    }
+    println!("       = {}", report.estimate);
+    println!();
+}
 
-        for square in &squares {
-            square.compute(&mut cpass);
-        }
+/// Prints the theoretical occupancy and what went into it, as [`Occupancy`] works out.
+fn print_occupancy(occupancy: &Occupancy) {
+    let [x, y] = occupancy.workgroup_size;
+    println!(
+        "workgroup = {} x {} ({} invocations, {:.0}% of the adapter's {})",
+        x,
+        y,
+        occupancy.invocations,
+        occupancy.workgroup_share * 100.0,
+        occupancy.max_invocations
+    );
+    println!(
+        "waves = {} of {} lanes, {:.1}% filled",
+        occupancy.waves,
+        WAVE_WIDTH,
+        occupancy.wave_fill * 100.0
+    );
+    println!("busy threads = {:.1}%", occupancy.busy * 100.0);
+    let [x, y] = occupancy.dispatch_dims;
+    println!(
+        "dispatch = {} x {} workgroups for each of {} squares (up to {} along each axis)",
+        x, y, occupancy.squares, occupancy.max_dispatch_dim
+    );
+    println!("occupancy = {:.1}%", occupancy.occupancy * 100.0);
+}
+
+/// Counts the lattice at every whole radius below `args.size` and prints the mean error of
+/// Gauss's circle problem for each class of radii modulo `modulus`.
+///
+/// The quarter holds the `2r - 1` points on the axes once each, so the whole disc holds
+/// `4 count - 4r + 1` points, and the error is that less `pi r^2`. Once the axes are taken
+/// out like this, what's left depends on how many ways `r^2` is a sum of two squares, as
+/// those points on the circle are left out.
+fn residue_study(args: &Args, gpu: &Gpu, modulus: u32) {
+    let mut errors = vec![(0, 0.0); modulus as usize];
+    for radius in 1..args.size {
+        let count = args.lattice(radius + 1).count(gpu).unwrap_or_else(fail);
+        let disc = 4 * count - 4 * radius as u64 + 1;
+        let error = disc as f64 - PI * (radius * radius) as f64;
+
+        let (radii, sum) = &mut errors[radius % modulus as usize];
+        *radii += 1;
+        *sum += error;
     }
-    for square in &squares {
-        square.copy(&mut encoder);
+
+    println!("residue  radii  mean error");
+    for (residue, (radii, sum)) in errors.iter().enumerate() {
+        match radii {
+            0 => println!("{:>7}  {:>5}  -", residue, radii),
+            _ => println!("{:>7}  {:>5}  {:+.3}", residue, radii, sum / *radii as f64),
+        }
     }
-    queue.submit(Some(encoder.finish()));
-    for square in &squares {
-        square.map();
+}
+
+/// Counts the lattice leaving out the points on the circle, as usual, and then taking them
+/// in, and prints both estimates and the gap between them, checking the gap is exactly the
+/// points the CPU finds on the circle.
+fn boundary_both(args: &Args, gpu: &Gpu, lattice: &Lattice) {
+    let strict = lattice.count(gpu).unwrap_or_else(fail);
+    let inclusive = Lattice {
+        inclusive: true,
+        ..*lattice
     }
-    instance.poll_all(true);
+    .count(gpu)
+    .unwrap_or_else(fail);
     println!("GPU Done!");
 
-    let squares = squares.iter().map(|s| s.get_total()).collect::<Vec<_>>();
+    let on_circle = cpu::circle_points(lattice.counted_radius());
+    assert!(
+        inclusive.checked_sub(strict) == Some(on_circle),
+        "Inclusive Count {} Isn't The Strict Count {} And {} Points On The Circle",
+        inclusive,
+        strict,
+        on_circle
+    );
+
+    let report = |count| Report {
+        n: lattice.size as u64,
+        fraction: lattice.fraction(count),
+        estimate: lattice.estimate(count),
+        constant: PI,
+        standard_error: None,
+    };
+    println!("strict    = {}", format_estimate(args, &report(strict)));
+    println!("inclusive = {}", format_estimate(args, &report(inclusive)));
+    println!(
+        "difference = {:e} from {} points on the circle",
+        lattice.estimate(on_circle),
+        on_circle
+    );
+}
+
+/// Counts the lattice at `size`, twice that and so on up to `max`, printing a `--compact`
+/// line for each.
+///
+/// With `--resume-sweep`, every line is also appended to the file as soon as its size is
+/// done, and sizes already in the file are skipped, so an interrupted sweep picks up at
+/// the first size it hadn't finished. The file is nothing but those `--compact` lines,
+/// `size numerator denominator pi error` separated by tabs, one per size in the order
+/// they were counted. A last line cut short by the interruption is dropped and redone.
+///
+/// Returns whether every size was counted, which is only ever not so with
+/// `--format ndjson-errors`. Failed sizes stay out of the file, to be tried again on resume.
+fn sweep(args: &Args, gpu: &Gpu, max: usize) -> bool {
+    let mut done = HashSet::new();
+    let mut file = args.resume_sweep.as_ref().map(|path| {
+        let previous = fs::read_to_string(path).unwrap_or_default();
+
+        let mut complete = String::new();
+        for line in previous.split_inclusive('\n') {
+            let size = line
+                .split('\t')
+                .next()
+                .and_then(|x| x.parse::<usize>().ok());
+            match size {
+                Some(size) if line.ends_with('\n') && line.split('\t').count() == 5 => {
+                    done.insert(size);
+                    complete.push_str(line);
+                }
+                _ => break,
+            }
+        }
+        if !done.is_empty() {
+            eprintln!("resuming sweep with {} sizes already done", done.len());
+        }
+
+        // rewritten, not appended to, in case the last line was cut short
+        fs::write(path, &complete).expect("Couldn't Write Sweep File");
+        OpenOptions::new()
+            .append(true)
+            .open(path)
+            .expect("Couldn't Open Sweep File")
+    });
+
+    let widths = aligned_widths(args, max);
+    if let Format::Aligned = args.format {
+        println!(
+            "{}",
+            aligned_row(["size", "numerator", "denominator", "pi", "error"], widths)
+        );
+    }
+
+    let mut succeeded = true;
+    let mut size = args.size;
+    while size <= max {
+        if done.contains(&size) {
+            size *= 2;
+            continue;
+        }
+
+        let count = || lattice_report(args, gpu, &args.lattice(size));
+        let report = match args.format {
+            Format::NdjsonErrors => {
+                catch_panic(count).and_then(|report| report.map_err(|error| error.to_string()))
+            }
+            _ => Ok(count().unwrap_or_else(fail)),
+        };
+
+        match report {
+            Ok(report) => {
+                let line = compact_line(args, &report);
+                match args.format {
+                    Format::Json | Format::NdjsonErrors => {
+                        println!("{}", ndjson_line(args, &report))
+                    }
+                    Format::Latex => println!("{}", latex_line(args, &report)),
+                    Format::Aligned => {
+                        let cells = line.split('\t').collect::<Vec<_>>();
+                        println!("{}", aligned_row(cells.try_into().unwrap(), widths));
+                    }
+                    _ => println!("{}", line),
+                }
+
+                if let Some(file) = &mut file {
+                    writeln!(file, "{}", line).expect("Couldn't Write Sweep File");
+                    file.sync_data().expect("Couldn't Write Sweep File");
+                }
+            }
+            Err(message) => {
+                println!(
+                    "{{\"size\": {}, \"error\": {}}}",
+                    size,
+                    json_string(&message)
+                );
+                succeeded = false;
+            }
+        }
+
+        size *= 2;
+    }
+
+    succeeded
+}
+
+/// Prints what produced this build's results: the versions, the adapter that would be used
+/// and a hash of every shader, so a changed shader shows up even without a version bump.
+async fn version_info(args: &Args) {
+    let info = Gpu::acquire(Features::empty(), args.adapter_retries)
+        .await
+        .ok()
+        .map(|gpu| gpu.info);
+    let shaders = version::SHADERS.map(|(name, source)| (name, version::fnv1a(source.as_bytes())));
+
+    match args.format {
+        Format::Json | Format::NdjsonErrors => {
+            let adapter = match &info {
+                Some(info) => format!(
+                    "{{\"name\": {}, \"backend\": \"{:?}\", \"type\": \"{:?}\"}}",
+                    json_string(&info.name),
+                    info.backend,
+                    info.device_type
+                ),
+                None => "null".to_string(),
+            };
+            let shaders = shaders
+                .iter()
+                .map(|(name, hash)| format!("\"{}\": \"{:016x}\"", name, hash))
+                .collect::<Vec<_>>()
+                .join(", ");
+
+            println!(
+                "{{\"picalc\": \"{}\", \"wgpu\": \"{}\", \"adapter\": {}, \"shaders\": {{{}}}}}",
+                version::VERSION,
+                version::WGPU_VERSION,
+                adapter,
+                shaders
+            );
+        }
+        Format::Plain | Format::Compact | Format::Aligned | Format::Latex => {
+            println!("picalc {}", version::VERSION);
+            println!("wgpu {}", version::WGPU_VERSION);
+            match &info {
+                Some(info) => println!(
+                    "adapter {} ({:?}, {:?})",
+                    info.name, info.backend, info.device_type
+                ),
+                None => println!("adapter none"),
+            }
+            for (name, hash) in shaders {
+                println!("{} {:016x}", name, hash);
+            }
+        }
+    }
+}
 
-    let full = ((size / 8) * (size / 8)) as u32;
+/// Counts the same lattice on every adapter and prints them fastest first. Each adapter
+/// gets a few untimed runs to compile its pipelines and wake up, then reports the median
+/// of the timed ones.
+async fn bench_adapters(args: &Args, size: usize) {
+    const WARMUPS: usize = 2;
+    const RUNS: usize = 5;
 
-    #[rustfmt::skip]
-    #[allow(clippy::identity_op)]
-    let total =
-        full       + full       + full       + full       + full       + full       + full       + squares[0] + 
-        full       + full       + full       + full       + full       + full       + full       + squares[1] + 
-        full       + full       + full       + full       + full       + full       + full       + squares[2] + 
-        full       + full       + full       + full       + full       + full       + squares[4] + squares[3] +
-        full       + full       + full       + full       + full       + full       + squares[5] + 0          + 
-        full       + full       + full       + full       + full       + squares[7] + squares[6] + 0          + 
-        full       + full       + full       + squares[4] + squares[5] + squares[6] + 0          + 0          +
-        squares[0] + squares[1] + squares[2] + squares[3] + 0          + 0          + 0          + 0
-    ;
+    let lattice = args.lattice(size);
+
+    let mut results = Vec::new();
+    for (info, gpu) in Gpu::all().await {
+        let name = format!("{} ({:?})", info.name, info.backend);
+
+        // wgpu panics on device errors and a count can fail outright, neither of which
+        // should take the other adapters down
+        let elapsed = gpu.ok().and_then(|gpu| {
+            panic::catch_unwind(AssertUnwindSafe(|| {
+                for _ in 0..WARMUPS {
+                    lattice.count(&gpu).ok()?;
+                }
+
+                let mut times = (0..RUNS)
+                    .map(|_| {
+                        let start = Instant::now();
+                        lattice.count(&gpu).ok()?;
+                        Some(start.elapsed())
+                    })
+                    .collect::<Option<Vec<_>>>()?;
+                times.sort();
+                Some(times[RUNS / 2])
+            }))
+            .ok()
+            .flatten()
+        });
+        results.push((name, elapsed));
+    }
+
+    // fastest first, failures last
+    results.sort_by_key(|(_, elapsed)| elapsed.unwrap_or(Duration::MAX));
+
+    println!("rank  adapter{}  time", " ".repeat(41));
+    for (rank, (name, elapsed)) in results.iter().enumerate() {
+        match elapsed {
+            Some(elapsed) => println!(
+                "{:>4}  {:<48}  {:.3} ms",
+                rank + 1,
+                name,
+                elapsed.as_secs_f64() * 1e3
+            ),
+            None => println!("{:>4}  {:<48}  failed", "-", name),
+        }
+    }
+}
 
-    println!("pi = {}/{}", (total as u64) * 4, (size - 1) * (size - 1));
+async fn run() {
+    let args = Args::parse();
+
+    env_logger::init();
+
+    if args.version_info {
+        version_info(&args).await;
+        return;
+    }
+
+    if let Some(size) = args.bench_adapters {
+        bench_adapters(&args, size).await;
+        return;
+    }
+
+    if args.fast {
+        let start = Instant::now();
+        let lattice = args.lattice(args.size);
+        let total = lattice.fast_count();
+        let report = Report {
+            n: lattice.size as u64,
+            fraction: lattice.fraction(total),
+            estimate: lattice.estimate(total),
+            constant: args.predicate.constant(),
+            standard_error: None,
+        };
+        finish(&args, start.elapsed(), &report);
+        return;
+    }
+
+    let features = match args.map_storage {
+        true => Features::MAPPABLE_PRIMARY_BUFFERS,
+        false => Features::empty(),
+    };
+    let gpu = acquire(&args, features).await.unwrap_or_else(fail);
+    if !gpu.device.features().contains(features) {
+        eprintln!("warning: the adapter can't map storage buffers, copying them as usual");
+    }
+
+    let start = Instant::now();
+    let report = match args.method {
+        Method::Lattice => {
+            let lattice = args.lattice(args.size);
+            let radius = lattice.radius;
+
+            if let Some(bins) = args.distance_histogram {
+                let histogram = lattice.distance_histogram(&gpu, bins).unwrap_or_else(fail);
+                println!("GPU Done!");
+
+                if args.verify {
+                    verify(&args, &gpu, &lattice, histogram.iter().sum()).unwrap_or_else(fail);
+                }
+
+                let bin_width = args.predicate.extent(radius) / bins as f64;
+                for (i, count) in histogram.iter().enumerate() {
+                    let lower = i as f64 * bin_width;
+                    println!("{:.3} {:.3} {}", lower, lower + bin_width, count);
+                }
+                return;
+            }
+
+            if args.occupancy {
+                print_occupancy(&lattice.occupancy(&gpu.device.limits()));
+                return;
+            }
+
+            if args.boundary_both {
+                boundary_both(&args, &gpu, &lattice);
+                return;
+            }
+
+            if let Some(modulus) = args.residue_study {
+                residue_study(&args, &gpu, modulus);
+                return;
+            }
+
+            if let Some(max) = args.sweep {
+                if !sweep(&args, &gpu, max) {
+                    process::exit(1);
+                }
+                return;
+            }
+
+            lattice_report(&args, &gpu, &lattice).unwrap_or_else(fail)
+        }
+        Method::MonteCarlo => {
+            let monte_carlo = MonteCarlo::new(&gpu, args.seed);
+
+            if args.convergence {
+                monte_carlo.progressive(&gpu, args.samples, |samples, hits| {
+                    let estimate = Fraction::new(hits * 4, samples).to_f64();
+                    print!("{} {:.3e}", samples, (estimate - PI).abs());
+                    if args.show_error {
+                        print!(" {:.3e}", monte_carlo::standard_error(samples, hits));
+                    }
+                    println!();
+                });
+                return;
+            }
+
+            let (samples, hits) = match &args.sample_points {
+                Some(points) => (
+                    points.points.len() as u64,
+                    Sampled::new(&gpu, points).hits(&gpu),
+                ),
+                None => (args.samples, monte_carlo.hits(&gpu, 0, args.samples)),
+            };
+            status(&args, "GPU Done!");
+
+            let fraction = Fraction::new(hits * 4, samples);
+            let standard_error = monte_carlo::standard_error(samples, hits);
+
+            if let (true, Some(points)) = (args.verify, &args.sample_points) {
+                let expected = cpu::sample_hits(&points.points);
+                assert!(
+                    hits == expected,
+                    "GPU and CPU Hits Differ: {} != {}",
+                    hits,
+                    expected
+                );
+                status(&args, "CPU Agrees!");
+            } else if args.verify {
+                // a normal estimate strays past 5 standard errors about once in 2 million runs
+                let deviations = (fraction.to_f64() - PI).abs() / standard_error;
+                assert!(
+                    deviations < 5.0,
+                    "Pi Is {:.1} Standard Errors Away",
+                    deviations
+                );
+                status(
+                    &args,
+                    &format!("Pi Within {:.1} Standard Errors!", deviations),
+                );
+            }
+
+            Report {
+                n: samples,
+                fraction: Some(fraction),
+                estimate: fraction.to_f64(),
+                constant: PI,
+                standard_error: Some(standard_error),
+            }
+        }
+        Method::Polygon => {
+            let mut polygon = Polygon::new(&gpu);
+            polygon.compensated = args.kahan;
+
+            if args.convergence {
+                let mut sides = 3;
+                while sides <= args.sides {
+                    let error = (polygon.area(&gpu, sides) - PI).abs();
+                    println!("{} {:.3e}", sides, error);
+                    sides *= 2;
+                }
+                return;
+            }
+
+            let estimate = polygon.area(&gpu, args.sides);
+            status(&args, "GPU Done!");
+
+            if args.verify {
+                // f32 rounding in each term adds up to around 1e-7 per edge at worst
+                let expected = cpu::polygon_area(args.sides);
+                let tolerance = 1e-7 * args.sides as f64;
+                assert!(
+                    (estimate - expected).abs() <= tolerance,
+                    "GPU and CPU Areas Differ: {} != {}",
+                    estimate,
+                    expected
+                );
+                status(&args, "CPU Agrees!");
+            }
+
+            Report {
+                n: args.sides as u64,
+                fraction: None,
+                estimate,
+                constant: PI,
+                standard_error: None,
+            }
+        }
+        Method::Leibniz => {
+            let mut leibniz = Leibniz::new(&gpu);
+            leibniz.compensated = args.kahan;
+
+            if args.convergence {
+                let mut terms = 1;
+                while terms <= args.terms {
+                    let error = (leibniz.estimate(&gpu, terms) - PI).abs();
+                    println!("{} {:.3e}", terms, error);
+                    terms *= 2;
+                }
+                return;
+            }
+
+            let estimate = leibniz.estimate(&gpu, args.terms);
+            status(&args, "GPU Done!");
+
+            if args.verify {
+                // every f32 partial is positive, so their rounding can't cancel anything out
+                let expected = cpu::leibniz(args.terms);
+                let tolerance = 1e-6;
+                assert!(
+                    (estimate - expected).abs() <= tolerance,
+                    "GPU and CPU Sums Differ: {} != {}",
+                    estimate,
+                    expected
+                );
+                status(&args, "CPU Agrees!");
+            }
+
+            Report {
+                n: args.terms,
+                fraction: None,
+                estimate,
+                constant: PI,
+                standard_error: None,
+            }
+        }
+    };
+
+    finish(&args, start.elapsed(), &report);
+}
+
+/// The GPU to run on, traced into `--trace`'s directory if there is one.
+#[cfg(feature = "trace")]
+async fn acquire(args: &Args, features: Features) -> Result<Gpu, PiError> {
+    match &args.trace {
+        Some(dir) => {
+            fs::create_dir_all(dir).expect("Couldn't Create Trace Directory");
+            Gpu::acquire_with_trace(features, args.adapter_retries, dir.as_ref()).await
+        }
+        None => Gpu::acquire(features, args.adapter_retries).await,
+    }
+}
+
+#[cfg(not(feature = "trace"))]
+async fn acquire(args: &Args, features: Features) -> Result<Gpu, PiError> {
+    Gpu::acquire(features, args.adapter_retries).await
+}
+
+/// Hands a single estimate on to wherever the options ask for it, `elapsed` being how long
+/// it took to make.
+fn finish(args: &Args, elapsed: Duration, report: &Report) {
+    if let Some(path) = &args.metrics_file {
+        write_metrics(path, elapsed, report).expect("Couldn't Write Metrics File");
+    }
+
+    match &args.shared_file {
+        Some(path) => write_shared(args, path, report).expect("Couldn't Write Shared File"),
+        None => print_report(args, report),
+    }
 }
@@ -0,0 +1,36 @@
+use std::mem::size_of;
+
+use bytemuck::Pod;
+
+// WGSL lays out every scalar little endian whatever the host, and the casts below read
+// them in the host's order
+const _: () = assert!(
+    cfg!(target_endian = "little"),
+    "Mapped Buffers Need A Little Endian Host"
+);
+
+/// Reads the bytes of a mapped buffer as the scalars a shader wrote to it.
+///
+/// The bytes are cast in place, so this leans on the host being little endian like WGSL,
+/// which every host wgpu runs on is and which the build checks. A big endian host would
+/// need the bytes of each scalar swapped here, which is why nothing else reinterprets
+/// mapped memory itself. Bitpacked words are unaffected either way, their bits being
+/// numbered within the word rather than by byte.
+pub fn words<T: Pod>(bytes: &[u8]) -> &[T] {
+    debug_assert!(
+        bytes.len().is_multiple_of(size_of::<T>()),
+        "Mapped Range Of {} Bytes Isn't Whole Words",
+        bytes.len()
+    );
+    bytemuck::cast_slice(bytes)
+}
+
+/// Like [`words`], for writing a buffer mapped at creation.
+pub fn words_mut<T: Pod>(bytes: &mut [u8]) -> &mut [T] {
+    debug_assert!(
+        bytes.len().is_multiple_of(size_of::<T>()),
+        "Mapped Range Of {} Bytes Isn't Whole Words",
+        bytes.len()
+    );
+    bytemuck::cast_slice_mut(bytes)
+}
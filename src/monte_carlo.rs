@@ -0,0 +1,342 @@
+use std::{
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use wgpu::{include_wgsl, util::DeviceExt, Buffer, BufferUsages};
+
+use crate::{
+    fraction::Fraction,
+    gpu::Gpu,
+    reduction::{Reduction, THREADS},
+};
+
+/// Samples drawn by a single dispatch. Kept well below `u32::MAX` so a thread's sample
+/// index can't wrap while it strides through the dispatch.
+const MAX_DISPATCH_SAMPLES: u64 = 1 << 31;
+
+/// Share of what's left of a budget a [`Stepper`] plans to fill, leaving room for batches
+/// running a little slower than the last.
+const BUDGET_HEADROOM: f64 = 0.9;
+
+/// Estimates pi by drawing uniform points from the unit square and counting how many land
+/// inside the quarter circle.
+///
+/// Sample `n` of a given seed is always the same point, so drawing `0..a` followed by
+/// `a..b` gives exactly the same hits as drawing `0..b` at once.
+pub struct MonteCarlo {
+    reduction: Reduction,
+    seed: u32,
+}
+
+impl MonteCarlo {
+    pub fn new(gpu: &Gpu, seed: u32) -> Self {
+        Self {
+            reduction: Reduction::new(gpu, include_wgsl!("monte_carlo.wgsl")),
+            seed,
+        }
+    }
+
+    /// Draws samples `first..first + count` and returns how many of them landed inside the
+    /// quarter circle.
+    pub fn hits(&self, gpu: &Gpu, first: u64, count: u64) -> u64 {
+        let end = first + count;
+        let dispatches = (first..end)
+            .step_by(MAX_DISPATCH_SAMPLES as usize)
+            .map(|first| {
+                let count = (end - first).min(MAX_DISPATCH_SAMPLES);
+                [self.seed, first as u32, (first >> 32) as u32, count as u32]
+            });
+
+        self.reduction
+            .run_all(gpu, dispatches, |hits| {
+                hits.iter().map(|&x| x as u64).sum::<u64>()
+            })
+            .iter()
+            .sum()
+    }
+
+    /// Draws `samples` points in batches, each doubling the running total, and calls
+    /// `on_batch(samples_so_far, hits_so_far)` after every batch.
+    ///
+    /// The standard error of the estimate shrinks like `1/sqrt(n)`, so plotting
+    /// `|estimate - pi|` against the sample count on log-log axes gives a line of slope
+    /// `-1/2`: every extra digit of pi costs a hundred times the samples.
+    pub fn progressive(&self, gpu: &Gpu, samples: u64, mut on_batch: impl FnMut(u64, u64)) {
+        let mut drawn = 0;
+        let mut hits = 0;
+
+        while drawn < samples {
+            let next = (drawn * 2).max(THREADS as u64).min(samples);
+            hits += self.hits(gpu, drawn, next - drawn);
+            drawn = next;
+
+            on_batch(drawn, hits);
+        }
+    }
+}
+
+/// Sample points given up front rather than drawn, so a count depends on nothing but the
+/// GPU's float comparison. Read from text with one `x y` point per line, with blank lines
+/// and lines starting with `#` skipped. There has to be at least one.
+#[derive(Clone, Default, PartialEq, Debug)]
+pub struct Points {
+    pub points: Vec<[f32; 2]>,
+}
+
+impl FromStr for Points {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut points = Vec::new();
+
+        for line in s.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let fields = line.split_whitespace().collect::<Vec<_>>();
+            let [x, y] = fields[..] else {
+                return Err(());
+            };
+            let point: [f32; 2] = [x.parse().map_err(|_| ())?, y.parse().map_err(|_| ())?];
+            if !point.iter().all(|x| x.is_finite()) {
+                return Err(());
+            }
+            points.push(point);
+        }
+
+        match points.is_empty() {
+            true => Err(()),
+            false => Ok(Self { points }),
+        }
+    }
+}
+
+/// Counts how many of a fixed set of [`Points`] land inside the quarter circle, the same
+/// test [`MonteCarlo`] puts its drawn points to. With no generator involved the hits
+/// should be exactly [`cpu::sample_hits`](crate::cpu::sample_hits) on every backend.
+///
+/// The points go to the GPU in a single storage buffer, so there can't be more than the
+/// adapter binds at once, 16 million on the default limits.
+pub struct Sampled {
+    reduction: Reduction,
+    _buffer: Buffer,
+    count: u32,
+}
+
+impl Sampled {
+    pub fn new(gpu: &Gpu, points: &Points) -> Self {
+        let buffer = gpu
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Samples Buffer"),
+                contents: bytemuck::cast_slice(&points.points),
+                usage: BufferUsages::STORAGE,
+            });
+
+        Self {
+            reduction: Reduction::with_input(gpu, include_wgsl!("samples.wgsl"), &buffer),
+            _buffer: buffer,
+            count: points.points.len() as u32,
+        }
+    }
+
+    pub fn hits(&self, gpu: &Gpu) -> u64 {
+        self.reduction.run(gpu, [0, self.count, 0, 0], |hits| {
+            hits.iter().map(|&x| x as u64).sum()
+        })
+    }
+}
+
+/// Draws a [`MonteCarlo`]'s samples a frame at a time, for showing the estimate converge
+/// without ever holding up the caller for longer than it can spare.
+///
+/// The stepper owns the running totals. Every call to [`Stepper::step`] carries on from the
+/// first sample the last one didn't draw, so after any number of calls with any budgets the
+/// totals are exactly what [`MonteCarlo::hits`] gives for that many samples from 0.
+pub struct Stepper {
+    monte_carlo: MonteCarlo,
+    drawn: u64,
+    hits: u64,
+    /// How long the last batch took per sample, to size the next one by.
+    seconds_per_sample: Option<f64>,
+}
+
+impl Stepper {
+    pub fn new(monte_carlo: MonteCarlo) -> Self {
+        Self {
+            monte_carlo,
+            drawn: 0,
+            hits: 0,
+            seconds_per_sample: None,
+        }
+    }
+
+    /// Draws as many more samples as are expected to fit in `budget` and returns the
+    /// estimate from every sample drawn so far.
+    ///
+    /// Samples go in multiples of [`THREADS`], each batch only started if the last one's
+    /// speed says it will finish in what's left of the budget. With nothing to go on yet,
+    /// the very first call draws a single batch of [`THREADS`] however long that takes, so
+    /// there is always an estimate to return. After that a call whose budget is too short
+    /// for even one batch draws nothing and returns the same estimate as the last.
+    pub fn step(&mut self, gpu: &Gpu, budget: Duration) -> Fraction {
+        let start = Instant::now();
+
+        loop {
+            let batch = match self.seconds_per_sample {
+                None => THREADS as u64,
+                Some(seconds) => {
+                    let left =
+                        budget.saturating_sub(start.elapsed()).as_secs_f64() * BUDGET_HEADROOM;
+                    let fits = (left / seconds) as u64 / THREADS as u64 * THREADS as u64;
+                    if fits == 0 {
+                        break;
+                    }
+                    fits
+                }
+            };
+
+            let began = Instant::now();
+            self.hits += self.monte_carlo.hits(gpu, self.drawn, batch);
+            self.drawn += batch;
+            self.seconds_per_sample = Some(began.elapsed().as_secs_f64() / batch as f64);
+        }
+
+        self.estimate()
+    }
+
+    /// The estimate from every sample drawn so far, `4 * hits / drawn`.
+    pub fn estimate(&self) -> Fraction {
+        Fraction::new(self.hits * 4, self.drawn)
+    }
+
+    pub fn drawn(&self) -> u64 {
+        self.drawn
+    }
+
+    pub fn hits(&self) -> u64 {
+        self.hits
+    }
+}
+
+/// The standard error of the estimate `4 * hits / samples`. Each sample is a Bernoulli
+/// trial landing inside with probability `p = pi / 4`, so the hit fraction has standard
+/// deviation `sqrt(p (1 - p) / n)`, scaled by the same factor of 4 as the estimate.
+pub fn standard_error(samples: u64, hits: u64) -> f64 {
+    let p = hits as f64 / samples as f64;
+    4.0 * (p * (1.0 - p) / samples as f64).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::PI;
+
+    use wgpu::Features;
+
+    use super::*;
+
+    fn gpu() -> Gpu {
+        Gpu::acquire_blocking(Features::empty(), 0).expect("No GPU Adapter For Tests")
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter"]
+    fn pi_is_within_standard_errors() {
+        let gpu = gpu();
+        let samples = 1 << 26;
+
+        // a normal estimate strays past 5 standard errors about once in 2 million seeds
+        for seed in 0..4 {
+            let hits = MonteCarlo::new(&gpu, seed).hits(&gpu, 0, samples);
+            let estimate = Fraction::new(hits * 4, samples).to_f64();
+            let deviations = (estimate - PI).abs() / standard_error(samples, hits);
+            assert!(deviations < 5.0, "seed {} is {:.1} away", seed, deviations);
+        }
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter"]
+    fn progressive_stops_at_samples() {
+        let gpu = gpu();
+        let monte_carlo = MonteCarlo::new(&gpu, 0);
+
+        // fewer samples than a dispatch has threads, then a few batches' worth
+        for samples in [1, 1000, THREADS as u64 * 5 + 1] {
+            let mut batches = Vec::new();
+            monte_carlo.progressive(&gpu, samples, |drawn, hits| batches.push((drawn, hits)));
+
+            assert_eq!(
+                batches.last(),
+                Some(&(samples, monte_carlo.hits(&gpu, 0, samples))),
+                "{} samples",
+                samples
+            );
+        }
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter"]
+    fn stepper_matches_hits() {
+        let gpu = gpu();
+        let monte_carlo = MonteCarlo::new(&gpu, 3);
+        let mut stepper = Stepper::new(MonteCarlo::new(&gpu, 3));
+
+        // the first step draws a batch even with no time for it
+        let budgets = [0, 1, 0, 20, 5, 0].map(Duration::from_millis);
+        for budget in budgets {
+            let estimate = stepper.step(&gpu, budget);
+            let drawn = stepper.drawn();
+
+            assert!(drawn >= THREADS as u64, "{:?}", budget);
+            assert_eq!(drawn % THREADS as u64, 0, "{:?}", budget);
+            assert_eq!(
+                stepper.hits(),
+                monte_carlo.hits(&gpu, 0, drawn),
+                "{:?}",
+                budget
+            );
+            assert_eq!(estimate, Fraction::new(stepper.hits() * 4, drawn));
+        }
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter"]
+    fn sampled_hits_match_every_backend() {
+        // 12 bits after the point, so every square adds up exactly, with the points on the
+        // edge that a sloppy comparison would let in
+        let mut state = 1u32;
+        let mut coordinate = || {
+            state = state.wrapping_mul(1664525).wrapping_add(1013904223);
+            (state >> 20) as f32 / 4096.0
+        };
+        let mut points = (0..1 << 16)
+            .map(|_| [coordinate(), coordinate()])
+            .collect::<Vec<_>>();
+        points.extend([[1.0, 0.0], [0.0, 1.0], [0.75, 0.0], [0.0, 0.0]]);
+        let points = Points { points };
+
+        let expected = crate::cpu::sample_hits(&points.points);
+        let gpus = pollster::block_on(Gpu::all());
+        assert!(!gpus.is_empty(), "No GPU Adapter For Tests");
+
+        for (info, gpu) in gpus {
+            let Ok(gpu) = gpu else {
+                continue;
+            };
+            let hits = Sampled::new(&gpu, &points).hits(&gpu);
+            assert_eq!(hits, expected, "{} ({:?})", info.name, info.backend);
+        }
+    }
+
+    #[test]
+    fn points_parse() {
+        let points = "# x y\n0.5 0.25\n\n  1 0  \n".parse::<Points>().unwrap();
+        assert_eq!(points.points, [[0.5, 0.25], [1.0, 0.0]]);
+
+        for bad in ["", "# nothing", "0.5", "0.5 0.5 0.5", "x 1", "inf 0"] {
+            assert!(bad.parse::<Points>().is_err(), "{:?}", bad);
+        }
+    }
+}
@@ -0,0 +1,46 @@
+struct Options {
+    seed: u32,
+    first_lo: u32,
+    first_hi: u32,
+    count: u32,
+}
+
+@group(0) @binding(0)
+var<uniform> options: Options;
+@group(0) @binding(1)
+var<storage, read_write> result: array<u32>;
+
+// Counter based generator (pcg3d, Jarzynski & Olano 2020), so every sample is a pure
+// function of its 64 bit index and the seed no matter how the draws are batched.
+fn pcg3d(input: vec3<u32>) -> vec3<u32> {
+    var v = input * 1664525u + 1013904223u;
+    v.x += v.y * v.z;
+    v.y += v.z * v.x;
+    v.z += v.x * v.y;
+    v ^= v >> vec3<u32>(16u);
+    v.x += v.y * v.z;
+    v.y += v.z * v.x;
+    v.z += v.x * v.y;
+    return v;
+}
+
+@compute @workgroup_size(64, 1, 1)
+fn main(
+    @builtin(global_invocation_id) invocation_id: vec3<u32>,
+    @builtin(num_workgroups) num_workgroups: vec3<u32>,
+) {
+    var threads = num_workgroups.x * 64u;
+    var hits = 0u;
+
+    for (var n = invocation_id.x; n < options.count; n += threads) {
+        var lo = options.first_lo + n;
+        var hi = options.first_hi + u32(lo < n);
+        var random = pcg3d(vec3<u32>(lo, hi, options.seed));
+
+        // 24 bits keeps the coordinates exact in an f32
+        var point = vec2<f32>(random.xy >> vec2<u32>(8u)) / 16777216.0;
+        hits += u32(dot(point, point) < 1.0);
+    }
+
+    result[invocation_id.x] = hits;
+}
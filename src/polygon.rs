@@ -0,0 +1,78 @@
+use wgpu::include_wgsl;
+
+use crate::{
+    gpu::Gpu,
+    reduction::{self, Reduction},
+};
+
+/// Estimates pi as the area of the regular polygon inscribed in the unit circle, found
+/// with the shoelace formula over its edges.
+///
+/// The exact area is `(n/2) sin(2 pi / n)`, which falls short of pi by about
+/// `2 pi^3 / (3 n^2)`. Doubling the sides quarters the error, where doubling the lattice's
+/// size only halves it at four times the work, so the polygon gets much further per unit
+/// of work. In practice the GPU's f32 trigonometry, not the sides, sets the error floor.
+pub struct Polygon {
+    reduction: Reduction,
+    /// Add the GPU's f32 areas up with compensated summation instead of one after another.
+    /// It makes next to no difference: each of the 65536 areas is only good to f32 to begin
+    /// with, and adding them up in f64 loses around `1e-12` at worst.
+    pub compensated: bool,
+}
+
+impl Polygon {
+    pub fn new(gpu: &Gpu) -> Self {
+        Self {
+            reduction: Reduction::new(gpu, include_wgsl!("polygon.wgsl")),
+            compensated: false,
+        }
+    }
+
+    /// The area of the regular polygon with `sides` vertices on the unit circle.
+    pub fn area(&self, gpu: &Gpu, sides: u32) -> f64 {
+        assert!(sides >= 3, "Polygon Needs At Least 3 Sides");
+
+        self.reduction.run(gpu, [sides, 0, 0, 0], |areas| {
+            let areas: &[f32] = bytemuck::cast_slice(areas);
+            reduction::sum(areas.iter().map(|&x| x as f64), self.compensated)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::PI;
+
+    use wgpu::Features;
+
+    use super::*;
+    use crate::cpu;
+
+    fn gpu() -> Gpu {
+        Gpu::acquire_blocking(Features::empty(), 0).expect("No GPU Adapter For Tests")
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter"]
+    fn area_converges() {
+        let gpu = gpu();
+        let polygon = Polygon::new(&gpu);
+
+        let mut last_error = f64::INFINITY;
+        for sides in (0..10).map(|k| 3 << k) {
+            let area = polygon.area(&gpu, sides);
+            let expected = cpu::polygon_area(sides);
+            assert!(
+                (area - expected).abs() <= 1e-7 * sides as f64,
+                "{} sides: {} != {}",
+                sides,
+                area,
+                expected
+            );
+
+            let error = (area - PI).abs();
+            assert!(error < last_error, "{} sides: {}", sides, error);
+            last_error = error;
+        }
+    }
+}
@@ -0,0 +1,32 @@
+struct Options {
+    sides: u32,
+}
+
+@group(0) @binding(0)
+var<uniform> options: Options;
+@group(0) @binding(1)
+var<storage, read_write> result: array<f32>;
+
+fn vertex(k: u32) -> vec2<f32> {
+    var angle = 6.283185307179586 * f32(k % options.sides) / f32(options.sides);
+    return vec2<f32>(cos(angle), sin(angle));
+}
+
+// Each thread adds up the shoelace terms x_k y_(k+1) - x_(k+1) y_k of every edge it
+// strides over
+@compute @workgroup_size(64, 1, 1)
+fn main(
+    @builtin(global_invocation_id) invocation_id: vec3<u32>,
+    @builtin(num_workgroups) num_workgroups: vec3<u32>,
+) {
+    var threads = num_workgroups.x * 64u;
+    var area = 0.0;
+
+    for (var k = invocation_id.x; k < options.sides; k += threads) {
+        var a = vertex(k);
+        var b = vertex(k + 1u);
+        area += a.x * b.y - b.x * a.y;
+    }
+
+    result[invocation_id.x] = area / 2.0;
+}
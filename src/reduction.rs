@@ -0,0 +1,247 @@
+use std::{collections::VecDeque, mem::size_of};
+
+use wgpu::{
+    BindGroup, Buffer, BufferDescriptor, BufferUsages, ComputePipeline, Features, MapMode,
+    ShaderModuleDescriptor, SubmissionIndex,
+};
+
+use crate::{gpu::Gpu, mapped};
+
+const WORKGROUP_SIZE: u32 = 64;
+const WORKGROUPS: u32 = 1024;
+
+/// Threads in every dispatch, each of which leaves one partial result behind.
+pub const THREADS: u32 = WORKGROUP_SIZE * WORKGROUPS;
+
+/// Words of uniform options a reduction shader gets at binding 0.
+pub const OPTIONS: usize = 4;
+
+/// Dispatches that can be in flight at once, each with its own region of partials.
+const RING: usize = 3;
+
+/// Adds up `values`, with Neumaier's variant of Kahan summation if `compensated` is set.
+/// That carries the low bits each addition rounds off in a second term that's added back at
+/// the end, and unlike plain Kahan summation keeps them when the value being added is the
+/// larger, as the Leibniz series' alternating partials often are.
+pub fn sum(values: impl IntoIterator<Item = f64>, compensated: bool) -> f64 {
+    if !compensated {
+        return values.into_iter().sum();
+    }
+
+    let mut sum = 0.0;
+    let mut compensation = 0.0;
+    for x in values {
+        let t = sum + x;
+        compensation += match sum.abs() >= x.abs() {
+            true => (sum - t) + x,
+            false => (x - t) + sum,
+        };
+        sum = t;
+    }
+    sum + compensation
+}
+
+/// A shader run over a fixed grid of [`THREADS`] threads, each writing one 32 bit partial
+/// result to binding 1 for the host to add up. The shader strides through its work by
+/// `THREADS`, so the grid never depends on how much work there is.
+///
+/// A run of several dispatches goes through a ring of [`RING`] regions, so the host adds up
+/// one region's partials while the GPU fills the next. Where the device can map storage
+/// buffers the shader writes each region's mappable buffer directly, and elsewhere it
+/// writes a single storage buffer that's copied into the region after every dispatch.
+pub struct Reduction {
+    pipeline: ComputePipeline,
+    options_buffer: Buffer,
+    /// Written by every dispatch and copied out, unless the regions are written directly.
+    storage_buffer: Option<Buffer>,
+    regions: Vec<Region>,
+}
+
+/// Where one dispatch's partials end up for the host to map.
+struct Region {
+    bind_group: BindGroup,
+    buffer: Buffer,
+}
+
+impl Reduction {
+    pub fn new(gpu: &Gpu, shader: ShaderModuleDescriptor) -> Self {
+        Self::build(gpu, shader, None)
+    }
+
+    /// Like [`Reduction::new`], for a shader that also reads `input` at binding 2.
+    pub fn with_input(gpu: &Gpu, shader: ShaderModuleDescriptor, input: &Buffer) -> Self {
+        Self::build(gpu, shader, Some(input))
+    }
+
+    fn build(gpu: &Gpu, shader: ShaderModuleDescriptor, input: Option<&Buffer>) -> Self {
+        let shader = gpu.device.create_shader_module(shader);
+
+        let pipeline = gpu
+            .device
+            .create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: None,
+                layout: None,
+                module: &shader,
+                entry_point: "main",
+            });
+
+        let options_buffer = gpu.device.create_buffer(&BufferDescriptor {
+            label: Some("Reduction Options Buffer"),
+            size: (size_of::<u32>() * OPTIONS) as u64,
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let partials_size = (size_of::<u32>() as u32 * THREADS) as u64;
+        let direct = gpu
+            .device
+            .features()
+            .contains(Features::MAPPABLE_PRIMARY_BUFFERS);
+
+        let storage_buffer = (!direct).then(|| {
+            gpu.device.create_buffer(&BufferDescriptor {
+                label: Some("Reduction Storage Buffer"),
+                size: partials_size,
+                usage: BufferUsages::STORAGE | BufferUsages::COPY_SRC,
+                mapped_at_creation: false,
+            })
+        });
+
+        let regions = (0..RING)
+            .map(|_| {
+                let buffer = gpu.device.create_buffer(&BufferDescriptor {
+                    label: Some("Reduction Region Buffer"),
+                    size: partials_size,
+                    usage: match direct {
+                        true => BufferUsages::STORAGE | BufferUsages::MAP_READ,
+                        false => BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+                    },
+                    mapped_at_creation: false,
+                });
+
+                let mut entries = vec![
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: options_buffer.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: storage_buffer
+                            .as_ref()
+                            .unwrap_or(&buffer)
+                            .as_entire_binding(),
+                    },
+                ];
+                if let Some(input) = input {
+                    entries.push(wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: input.as_entire_binding(),
+                    });
+                }
+
+                let bind_group = gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: None,
+                    layout: &pipeline.get_bind_group_layout(0),
+                    entries: &entries,
+                });
+
+                Region { bind_group, buffer }
+            })
+            .collect();
+
+        Self {
+            pipeline,
+            options_buffer,
+            storage_buffer,
+            regions,
+        }
+    }
+
+    /// Runs the shader once with the given options and hands its partials to `sum`.
+    pub fn run<T>(&self, gpu: &Gpu, options: [u32; OPTIONS], sum: impl FnMut(&[u32]) -> T) -> T {
+        self.run_all(gpu, [options], sum).pop().unwrap()
+    }
+
+    /// Runs the shader once for each of `options`, handing each dispatch's partials to `sum`
+    /// in order and returning what it made of them.
+    ///
+    /// Each dispatch's submission is the fence for its region. Once every region has a
+    /// dispatch in flight, the host waits for the oldest, adds it up and unmaps it before
+    /// reusing that region for the next dispatch, so a region is never written while it's
+    /// mapped and the GPU never waits on the host while there's work left to queue.
+    pub fn run_all<T>(
+        &self,
+        gpu: &Gpu,
+        options: impl IntoIterator<Item = [u32; OPTIONS]>,
+        mut sum: impl FnMut(&[u32]) -> T,
+    ) -> Vec<T> {
+        let mut results = Vec::new();
+        let mut in_flight = VecDeque::with_capacity(RING);
+
+        for (i, options) in options.into_iter().enumerate() {
+            if in_flight.len() == RING {
+                let (region, submission) = in_flight.pop_front().unwrap();
+                results.push(self.finish(gpu, region, submission, &mut sum));
+            }
+
+            let region = &self.regions[i % RING];
+            in_flight.push_back((region, self.submit(gpu, region, options)));
+        }
+        for (region, submission) in in_flight {
+            results.push(self.finish(gpu, region, submission, &mut sum));
+        }
+
+        results
+    }
+
+    fn submit(&self, gpu: &Gpu, region: &Region, options: [u32; OPTIONS]) -> SubmissionIndex {
+        // lands before this submission and after every earlier one, which read the last
+        gpu.queue
+            .write_buffer(&self.options_buffer, 0, bytemuck::cast_slice(&options));
+
+        let mut encoder = gpu
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        {
+            let mut cpass =
+                encoder.begin_compute_pass(&wgpu::ComputePassDescriptor { label: None });
+            cpass.set_pipeline(&self.pipeline);
+            cpass.set_bind_group(0, &region.bind_group, &[]);
+            cpass.dispatch_workgroups(WORKGROUPS, 1, 1);
+        }
+        if let Some(storage_buffer) = &self.storage_buffer {
+            encoder.copy_buffer_to_buffer(
+                storage_buffer,
+                0,
+                &region.buffer,
+                0,
+                region.buffer.size(),
+            );
+        }
+        let submission = gpu.queue.submit(Some(encoder.finish()));
+
+        region.buffer.slice(..).map_async(MapMode::Read, move |e| {
+            e.unwrap();
+        });
+
+        submission
+    }
+
+    fn finish<T>(
+        &self,
+        gpu: &Gpu,
+        region: &Region,
+        submission: SubmissionIndex,
+        sum: &mut impl FnMut(&[u32]) -> T,
+    ) -> T {
+        gpu.wait(submission);
+
+        let total = {
+            let data = region.buffer.slice(..).get_mapped_range();
+            sum(mapped::words(&data))
+        };
+        region.buffer.unmap();
+
+        total
+    }
+}
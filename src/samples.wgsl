@@ -0,0 +1,29 @@
+struct Options {
+    first: u32,
+    count: u32,
+    _pad0: u32,
+    _pad1: u32,
+}
+
+@group(0) @binding(0)
+var<uniform> options: Options;
+@group(0) @binding(1)
+var<storage, read_write> result: array<u32>;
+@group(0) @binding(2)
+var<storage, read> samples: array<vec2<f32>>;
+
+@compute @workgroup_size(64, 1, 1)
+fn main(
+    @builtin(global_invocation_id) invocation_id: vec3<u32>,
+    @builtin(num_workgroups) num_workgroups: vec3<u32>,
+) {
+    var threads = num_workgroups.x * 64u;
+    var hits = 0u;
+
+    for (var n = invocation_id.x; n < options.count; n += threads) {
+        var point = samples[options.first + n];
+        hits += u32(point.x * point.x + point.y * point.y < 1.0);
+    }
+
+    result[invocation_id.x] = hits;
+}
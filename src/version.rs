@@ -0,0 +1,22 @@
+//! What went into this build, for telling apart results from different ones.
+
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// The wgpu version Cargo.lock picked, or "unknown" without one.
+pub const WGPU_VERSION: &str = env!("PICALC_WGPU_VERSION");
+
+/// Every shader built in, by file name.
+pub const SHADERS: [(&str, &str); 5] = [
+    ("compute.wgsl", include_str!("compute.wgsl")),
+    ("monte_carlo.wgsl", include_str!("monte_carlo.wgsl")),
+    ("polygon.wgsl", include_str!("polygon.wgsl")),
+    ("leibniz.wgsl", include_str!("leibniz.wgsl")),
+    ("samples.wgsl", include_str!("samples.wgsl")),
+];
+
+/// 64 bit FNV-1a, which is plenty to notice a shader changed.
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}